@@ -17,6 +17,13 @@ pub mod config_grammar {
     include!(concat!(env!("OUT_DIR"), "/config_grammar.rs"));
 }
 
+mod database;
+mod edit;
+mod serialize;
+
+pub use database::{ConfigDatabase, MissingParent};
+pub use serialize::{from_config, from_config_class, to_config, PARENT_FIELD};
+
 /// Config
 ///
 /// # Examples
@@ -182,6 +189,8 @@ pub enum ConfigEntry {
     FloatEntry(f32),
     /// Int entry
     IntEntry(i32),
+    /// 64-bit int entry, for values that don't fit in an int
+    Int64Entry(i64),
     /// Array entry
     ArrayEntry(ConfigArray),
     /// Class entry
@@ -191,7 +200,8 @@ pub enum ConfigEntry {
 /// Config array
 #[derive(Debug, Clone)]
 pub struct ConfigArray {
-    is_expansion: bool,
+    /// Whether the array expands the inherited one (`foo[] += {...}`) instead of replacing it
+    pub is_expansion: bool,
     pub elements: Vec<ConfigArrayElement>,
 }
 
@@ -204,6 +214,8 @@ pub enum ConfigArrayElement {
     FloatElement(f32),
     /// Int element
     IntElement(i32),
+    /// 64-bit int element
+    Int64Element(i64),
     /// Array element
     ArrayElement(ConfigArray),
 }
@@ -214,6 +226,7 @@ impl ConfigArrayElement {
             ConfigArrayElement::StringElement(s) => s.len() + 2,
             ConfigArrayElement::FloatElement(_f) => 5,
             ConfigArrayElement::IntElement(_i) => 5,
+            ConfigArrayElement::Int64Element(_i) => 9,
             ConfigArrayElement::ArrayElement(a) => 1 + compressed_int_len(a.elements.len() as u32) +
                 usize::sum(a.elements.iter().map(|e| e.rapified_length()))
         }
@@ -221,6 +234,22 @@ impl ConfigArrayElement {
 }
 
 impl ConfigArray {
+    /// Creates an array that replaces the inherited one.
+    pub fn new(elements: Vec<ConfigArrayElement>) -> ConfigArray {
+        ConfigArray {
+            is_expansion: false,
+            elements,
+        }
+    }
+
+    /// Creates an array that expands the inherited one (`foo[] += {...}`).
+    pub fn expansion(elements: Vec<ConfigArrayElement>) -> ConfigArray {
+        ConfigArray {
+            is_expansion: true,
+            elements,
+        }
+    }
+
     fn write<O: Write>(&self, output: &mut O) -> Result<(), Error> {
         output.write_all(b"{")?;
         for (key, value) in self.elements.iter().enumerate() {
@@ -236,6 +265,9 @@ impl ConfigArray {
                 },
                 ConfigArrayElement::IntElement(i) => {
                     output.write_all(format!("{}", i).as_bytes())?;
+                },
+                ConfigArrayElement::Int64Element(i) => {
+                    output.write_all(format!("{}", i).as_bytes())?;
                 }
             }
             if key < self.elements.len() - 1 {
@@ -266,6 +298,11 @@ impl ConfigArray {
                     output.write_i32::<LittleEndian>(*i)?;
                     written += 5;
                 },
+                ConfigArrayElement::Int64Element(i) => {
+                    output.write_all(&[6])?;
+                    output.write_i64::<LittleEndian>(*i)?;
+                    written += 9;
+                },
                 ConfigArrayElement::ArrayElement(a) => {
                     output.write_all(&[3])?;
                     written += 1 + a.write_rapified(output)?;
@@ -291,6 +328,8 @@ impl ConfigArray {
                 elements.push(ConfigArrayElement::IntElement(input.read_i32::<LittleEndian>()?));
             } else if element_type == 3 {
                 elements.push(ConfigArrayElement::ArrayElement(ConfigArray::read_rapified(input)?));
+            } else if element_type == 6 {
+                elements.push(ConfigArrayElement::Int64Element(input.read_i64::<LittleEndian>()?));
             } else {
                 return Err(error!("Unrecognized array element type: {}", element_type));
             }
@@ -310,6 +349,7 @@ impl ConfigEntry {
             ConfigEntry::StringEntry(s) => s.len() + 3,
             ConfigEntry::FloatEntry(_f) => 6,
             ConfigEntry::IntEntry(_i) => 6,
+            ConfigEntry::Int64Entry(_i) => 10,
             ConfigEntry::ArrayEntry(a) => {
                 let len = 1 + compressed_int_len(a.elements.len() as u32) +
                     usize::sum(a.elements.iter().map(|e| e.rapified_length()));
@@ -366,6 +406,9 @@ impl ConfigClass {
                         ConfigEntry::IntEntry(i) => {
                             output.write_all(format!("{} = {};\n", key, i).as_bytes())?;
                         },
+                        ConfigEntry::Int64Entry(i) => {
+                            output.write_all(format!("{} = {};\n", key, i).as_bytes())?;
+                        },
                         ConfigEntry::ArrayEntry(ref a) => {
                             if a.is_expansion {
                                 output.write_all(format!("{}[] += ", key).as_bytes())?;
@@ -434,6 +477,12 @@ impl ConfigClass {
                             output.write_i32::<LittleEndian>(*i)?;
                             written += name.len() + 7;
                         },
+                        ConfigEntry::Int64Entry(i) => {
+                            output.write_all(&[1, 6])?;
+                            output.write_cstring(name)?;
+                            output.write_i64::<LittleEndian>(*i)?;
+                            written += name.len() + 11;
+                        },
                         ConfigEntry::ArrayEntry(a) => {
                             output.write_all(if a.is_expansion { &[5] } else { &[2] })?;
                             if a.is_expansion {
@@ -512,12 +561,14 @@ impl ConfigClass {
                     entries.push((name, ConfigEntry::FloatEntry(input.read_f32::<LittleEndian>()?)));
                 } else if subtype == 2 {
                     entries.push((name, ConfigEntry::IntEntry(input.read_i32::<LittleEndian>()?)));
+                } else if subtype == 6 {
+                    entries.push((name, ConfigEntry::Int64Entry(input.read_i64::<LittleEndian>()?)));
                 } else {
                     return Err(error!("Unrecognized variable entry subtype: {}.", subtype));
                 }
             } else if entry_type == 2 || entry_type == 5 {
                 if entry_type == 5 {
-                    input.seek(SeekFrom::Current(4))?;
+                    input.seek(SeekFrom::Current(4))?; // flags, written as 1
                 }
 
                 let name = input.read_cstring()?;
@@ -530,7 +581,7 @@ impl ConfigClass {
                 let class_entry = ConfigClass {
                     parent: String::from(""),
                     is_external: entry_type == 3,
-                    is_deletion: entry_type == 5,
+                    is_deletion: entry_type == 4,
                     entries: None
                 };
 
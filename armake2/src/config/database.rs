@@ -0,0 +1,365 @@
+//! Resolving inheritance across a set of configs
+//!
+//! Configs are merged in the order they are added, like Arma loads addons, and parents are then
+//! looked up the way Arma does: first in the class the child is defined in, including what that
+//! class inherits, then in the classes around it. That makes `class Turrets: Turrets` inside a
+//! vehicle refer to the `Turrets` of the vehicle's parent.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::*;
+
+/// Longest inheritance chain that is followed, which stops cyclic inheritance
+const MAX_DEPTH: usize = 256;
+
+/// Class whose parent can't be found, or is only declared as an external class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingParent {
+    /// Path of the class, like `CfgVehicles/B_Soldier_F`
+    pub class: String,
+    /// Name of the missing parent
+    pub parent: String,
+}
+
+impl fmt::Display for MissingParent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Class \"{}\" inherits from missing class \"{}\".", self.class, self.parent)
+    }
+}
+
+struct ClassNode {
+    path: Vec<String>,
+    /// Class this one is defined in
+    container: Option<usize>,
+    /// Classes defined in this one, by lowercase name
+    members: HashMap<String, usize>,
+    /// Resolved parent, or the name of a parent that can't be found
+    parent: Result<Option<usize>, String>,
+}
+
+/// A set of configs merged together, with inheritance resolved
+///
+/// # Examples
+///
+/// ```
+/// # use armake2::config::{Config, ConfigDatabase};
+/// let base = String::from("class CfgWeapons { class Rifle { hiddenSelections[] = {\"camo\"}; }; };");
+/// let addon = String::from("class CfgWeapons { class Rifle; class MyRifle: Rifle {}; };");
+///
+/// let database = ConfigDatabase::from_configs(vec![
+///     Config::from_string(base, None, &Vec::new()).unwrap(),
+///     Config::from_string(addon, None, &Vec::new()).unwrap(),
+/// ]);
+///
+/// assert!(database.get("CfgWeapons/MyRifle/hiddenSelections").is_some());
+/// assert_eq!(vec!["CfgWeapons/MyRifle"], database.subclasses("CfgWeapons/Rifle"));
+/// ```
+pub struct ConfigDatabase {
+    root: ConfigClass,
+    nodes: Vec<ClassNode>,
+}
+
+impl Default for ConfigDatabase {
+    fn default() -> Self {
+        ConfigDatabase::new()
+    }
+}
+
+impl ConfigDatabase {
+    /// Creates an empty database.
+    pub fn new() -> ConfigDatabase {
+        let mut database = ConfigDatabase {
+            root: ConfigClass::new(),
+            nodes: Vec::new(),
+        };
+        database.index();
+        database
+    }
+
+    /// Creates a database from configs, in load order.
+    pub fn from_configs<I: IntoIterator<Item = Config>>(configs: I) -> ConfigDatabase {
+        let mut database = ConfigDatabase::new();
+        for config in configs {
+            database.root.merge(config.into_inner());
+        }
+        database.index();
+        database
+    }
+
+    /// Adds a config on top of the ones added before it. See `ConfigClass::merge`.
+    pub fn add(&mut self, config: Config) {
+        self.root.merge(config.into_inner());
+        self.index();
+    }
+
+    /// Returns the merged configs, without inheritance resolved.
+    pub fn config(&self) -> &ConfigClass {
+        &self.root
+    }
+
+    /// Returns the path of the class at `path`, with the case of its definition. Classes on the
+    /// path may be inherited, e.g. `CfgVehicles/B_Soldier_F/HitPoints` can be defined in a parent
+    /// of `B_Soldier_F`.
+    pub fn class_path(&self, path: &str) -> Option<String> {
+        self.lookup(path).map(|index| self.path_of(index))
+    }
+
+    /// Returns the definition of the class at `path`. See `class_path`.
+    pub fn class(&self, path: &str) -> Option<&ConfigClass> {
+        self.lookup(path).and_then(|index| self.definition(index))
+    }
+
+    /// Returns the path of the parent of the class at `path`.
+    pub fn parent(&self, path: &str) -> Option<String> {
+        let index = self.lookup(path)?;
+        self.nodes[index].parent.clone().ok()?.map(|parent| self.path_of(parent))
+    }
+
+    /// Returns the paths of the parent of the class at `path`, its parent, and so on.
+    pub fn ancestors(&self, path: &str) -> Vec<String> {
+        match self.lookup(path) {
+            Some(index) => self.chain(index).skip(1).map(|ancestor| self.path_of(ancestor)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns whether the class at `path` inherits from the class at `ancestor`, directly or not.
+    pub fn inherits_from(&self, path: &str, ancestor: &str) -> bool {
+        match (self.lookup(path), self.lookup(ancestor)) {
+            (Some(index), Some(ancestor)) => self.chain(index).skip(1).any(|i| i == ancestor),
+            _ => false,
+        }
+    }
+
+    /// Returns the paths of every class that inherits from the class at `path`, directly or not,
+    /// in config order.
+    pub fn subclasses(&self, path: &str) -> Vec<String> {
+        let ancestor = match self.lookup(path) {
+            Some(index) => index,
+            None => return Vec::new(),
+        };
+
+        (0..self.nodes.len())
+            .filter(|&index| self.chain(index).skip(1).any(|i| i == ancestor))
+            .map(|index| self.path_of(index))
+            .collect()
+    }
+
+    /// Returns the effective value of the entry at `path`, which may be inherited. Array
+    /// expansions (`foo[] += {...}`) are applied to the inherited array.
+    ///
+    /// ```
+    /// # use armake2::config::{Config, ConfigDatabase, ConfigEntry};
+    /// let input = String::from("\
+    /// class CfgVehicles {
+    ///     class Car { hiddenSelections[] = {\"camo1\"}; };
+    ///     class Truck: Car { hiddenSelections[] += {\"camo2\"}; };
+    /// };");
+    ///
+    /// let database = ConfigDatabase::from_configs(vec![Config::from_string(input, None, &Vec::new()).unwrap()]);
+    ///
+    /// match database.get("CfgVehicles/Truck/hiddenSelections") {
+    ///     Some(ConfigEntry::ArrayEntry(array)) => assert_eq!(2, array.elements.len()),
+    ///     _ => panic!("Missing hiddenSelections[]"),
+    /// }
+    /// ```
+    pub fn get(&self, path: &str) -> Option<ConfigEntry> {
+        let mut names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+        let name = names.pop()?;
+        let index = self.lookup(&names.join("/"))?;
+
+        self.entry(index, name, 0)
+    }
+
+    /// Returns every class whose parent can't be found, in config order.
+    pub fn missing_parents(&self) -> Vec<MissingParent> {
+        self.nodes.iter().filter_map(|node| match &node.parent {
+            Err(parent) => Some(MissingParent {
+                class: node.path.join("/"),
+                parent: parent.clone(),
+            }),
+            Ok(_) => None,
+        }).collect()
+    }
+
+    fn entry(&self, index: usize, name: &str, depth: usize) -> Option<ConfigEntry> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let inherited = || match self.nodes[index].parent {
+            Ok(Some(parent)) => self.entry(parent, name, depth + 1),
+            _ => None,
+        };
+
+        match self.definition(index)?.get(name) {
+            Some(ConfigEntry::ArrayEntry(array)) if array.is_expansion => {
+                let mut elements = match inherited() {
+                    Some(ConfigEntry::ArrayEntry(inherited)) => inherited.elements,
+                    _ => Vec::new(),
+                };
+                elements.extend(array.elements.iter().cloned());
+
+                Some(ConfigEntry::ArrayEntry(ConfigArray::new(elements)))
+            },
+            Some(entry) => Some(entry.clone()),
+            None => inherited(),
+        }
+    }
+
+    fn lookup(&self, path: &str) -> Option<usize> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(0, |index, name| self.member(index, name))
+    }
+
+    /// Finds a class defined in, or inherited by, the class at `index`
+    fn member(&self, index: usize, name: &str) -> Option<usize> {
+        let key = name.to_lowercase();
+        self.chain(index).find_map(|class| self.nodes[class].members.get(&key).copied())
+    }
+
+    /// Iterates over a class and its ancestors
+    fn chain(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut next = Some(index);
+
+        std::iter::from_fn(move || {
+            let current = next?;
+            next = self.nodes[current].parent.clone().ok().flatten();
+            Some(current)
+        }).take(MAX_DEPTH)
+    }
+
+    fn definition(&self, index: usize) -> Option<&ConfigClass> {
+        self.root.class(&self.path_of(index))
+    }
+
+    fn path_of(&self, index: usize) -> String {
+        self.nodes[index].path.join("/")
+    }
+
+    /// Rebuilds the class tree and resolves every parent.
+    fn index(&mut self) {
+        let mut resolver = Resolver {
+            nodes: Vec::new(),
+            parents: Vec::new(),
+            states: Vec::new(),
+        };
+
+        resolver.add(&self.root, Vec::new(), None);
+
+        // Missing parents are kept in each class's state
+        for index in 0..resolver.nodes.len() {
+            let _ = resolver.resolve(index);
+        }
+
+        let Resolver { mut nodes, states, .. } = resolver;
+
+        for (node, state) in nodes.iter_mut().zip(states) {
+            if let State::Resolved(parent) = state {
+                node.parent = parent;
+            }
+        }
+
+        self.nodes = nodes;
+    }
+}
+
+enum State {
+    Unresolved,
+    Resolving,
+    Resolved(Result<Option<usize>, String>),
+}
+
+struct Resolver {
+    nodes: Vec<ClassNode>,
+    /// Parent name of each class, and whether it is only declared as an external class
+    parents: Vec<(String, bool)>,
+    states: Vec<State>,
+}
+
+impl Resolver {
+    fn add(&mut self, class: &ConfigClass, path: Vec<String>, container: Option<usize>) -> usize {
+        let index = self.nodes.len();
+
+        self.nodes.push(ClassNode {
+            path,
+            container,
+            members: HashMap::new(),
+            parent: Ok(None),
+        });
+        self.parents.push((class.parent.clone(), class.is_external));
+        self.states.push(State::Unresolved);
+
+        for (name, entry) in class.entries() {
+            if let ConfigEntry::ClassEntry(child) = entry {
+                if child.is_deletion {
+                    continue;
+                }
+
+                let mut path = self.nodes[index].path.clone();
+                path.push(name.clone());
+
+                let child_index = self.add(child, path, Some(index));
+                self.nodes[index].members.insert(name.to_lowercase(), child_index);
+            }
+        }
+
+        index
+    }
+
+    fn resolve(&mut self, index: usize) -> Result<Option<usize>, String> {
+        match &self.states[index] {
+            State::Resolved(parent) => return parent.clone(),
+            // Cyclic inheritance, which is cut here
+            State::Resolving => return Ok(None),
+            State::Unresolved => {},
+        }
+
+        self.states[index] = State::Resolving;
+        let parent = self.find_parent(index);
+        self.states[index] = State::Resolved(parent.clone());
+
+        parent
+    }
+
+    fn find_parent(&mut self, index: usize) -> Result<Option<usize>, String> {
+        let name = self.parents[index].0.clone();
+
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        let mut scope = self.nodes[index].container;
+
+        while let Some(container) = scope {
+            if let Some(parent) = self.member(container, &name, index) {
+                return if self.parents[parent].1 { Err(name) } else { Ok(Some(parent)) };
+            }
+
+            scope = self.nodes[container].container;
+        }
+
+        Err(name)
+    }
+
+    /// Finds a class named `name` defined in, or inherited by, the class at `index`, other than
+    /// the class at `skip`
+    fn member(&mut self, index: usize, name: &str, skip: usize) -> Option<usize> {
+        let key = name.to_lowercase();
+        let mut current = Some(index);
+
+        for _ in 0..MAX_DEPTH {
+            let class = current?;
+
+            if let Some(&member) = self.nodes[class].members.get(&key) {
+                if member != skip {
+                    return Some(member);
+                }
+            }
+
+            current = self.resolve(class).ok().flatten();
+        }
+
+        None
+    }
+}
@@ -0,0 +1,272 @@
+//! Looking up and editing config entries by path
+//!
+//! Paths are entry names separated by `/`, like `Mission/Entities/Item0/position`. Names are
+//! matched case-insensitively, like Arma does, and edits keep the order of the other entries.
+
+use std::io::Error;
+
+use super::*;
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/').filter(|name| !name.is_empty()).collect()
+}
+
+/// Splits a path into the path of the class and the name of the entry
+fn split_last(path: &str) -> Result<(Vec<&str>, &str), Error> {
+    let mut names = split_path(path);
+    let name = names.pop().ok_or_else(|| error!("Empty config path."))?;
+
+    Ok((names, name))
+}
+
+impl Config {
+    /// Returns the entry at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use armake2::config::{Config, ConfigEntry};
+    /// let input = String::from("class Mission { class Intel { year = 2035; }; };");
+    /// let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    ///
+    /// match config.get("mission/intel/YEAR") {
+    ///     Some(ConfigEntry::IntEntry(year)) => assert_eq!(2035, *year),
+    ///     _ => panic!("Missing year"),
+    /// }
+    /// ```
+    pub fn get(&self, path: &str) -> Option<&ConfigEntry> {
+        self.root_body.get(path)
+    }
+
+    /// Returns the entry at `path` mutably.
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut ConfigEntry> {
+        self.root_body.get_mut(path)
+    }
+
+    /// Returns the class at `path`.
+    pub fn class(&self, path: &str) -> Option<&ConfigClass> {
+        self.root_body.class(path)
+    }
+
+    /// Returns the class at `path` mutably.
+    pub fn class_mut(&mut self, path: &str) -> Option<&mut ConfigClass> {
+        self.root_body.class_mut(path)
+    }
+
+    /// Sets the entry at `path`. See `ConfigClass::set`.
+    pub fn set(&mut self, path: &str, entry: ConfigEntry) -> Result<Option<ConfigEntry>, Error> {
+        self.root_body.set(path, entry)
+    }
+
+    /// Removes the entry at `path`.
+    pub fn remove(&mut self, path: &str) -> Option<ConfigEntry> {
+        self.root_body.remove(path)
+    }
+
+    /// Merges another config into this one. See `ConfigClass::merge`.
+    pub fn merge(&mut self, other: Config) {
+        self.root_body.merge(other.root_body)
+    }
+}
+
+impl Default for ConfigClass {
+    fn default() -> Self {
+        ConfigClass::new()
+    }
+}
+
+impl ConfigClass {
+    /// Creates an empty class without a parent.
+    pub fn new() -> ConfigClass {
+        ConfigClass {
+            parent: String::new(),
+            is_external: false,
+            is_deletion: false,
+            entries: Some(Vec::new()),
+        }
+    }
+
+    /// Creates an empty class inheriting from `parent`.
+    pub fn with_parent<S: Into<String>>(parent: S) -> ConfigClass {
+        ConfigClass {
+            parent: parent.into(),
+            ..ConfigClass::new()
+        }
+    }
+
+    /// Returns the entries of the class, which are empty for external classes and deletions.
+    pub fn entries(&self) -> &[(String, ConfigEntry)] {
+        self.entries.as_deref().unwrap_or(&[])
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries().iter().position(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    fn child(&self, name: &str) -> Option<&ConfigEntry> {
+        self.entries().iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, entry)| entry)
+    }
+
+    fn child_mut(&mut self, name: &str) -> Option<&mut ConfigEntry> {
+        self.entries.as_mut()?.iter_mut().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, entry)| entry)
+    }
+
+    fn class_at(&self, names: &[&str]) -> Option<&ConfigClass> {
+        names.iter().try_fold(self, |class, name| match class.child(name)? {
+            ConfigEntry::ClassEntry(class) => Some(class),
+            _ => None,
+        })
+    }
+
+    fn class_at_mut(&mut self, names: &[&str]) -> Option<&mut ConfigClass> {
+        names.iter().try_fold(self, |class, name| match class.child_mut(name)? {
+            ConfigEntry::ClassEntry(class) => Some(class),
+            _ => None,
+        })
+    }
+
+    /// Returns the entry at `path`, relative to this class.
+    pub fn get(&self, path: &str) -> Option<&ConfigEntry> {
+        let (names, name) = split_last(path).ok()?;
+        self.class_at(&names)?.child(name)
+    }
+
+    /// Returns the entry at `path` mutably.
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut ConfigEntry> {
+        let (names, name) = split_last(path).ok()?;
+        self.class_at_mut(&names)?.child_mut(name)
+    }
+
+    /// Returns the class at `path`, or this class for an empty path.
+    pub fn class(&self, path: &str) -> Option<&ConfigClass> {
+        self.class_at(&split_path(path))
+    }
+
+    /// Returns the class at `path` mutably.
+    pub fn class_mut(&mut self, path: &str) -> Option<&mut ConfigClass> {
+        self.class_at_mut(&split_path(path))
+    }
+
+    /// Returns the class at `path`, appending empty classes for any that don't exist. Fails if
+    /// an entry on the path isn't a class.
+    pub fn class_or_insert(&mut self, path: &str) -> Result<&mut ConfigClass, Error> {
+        let mut class = self;
+
+        for name in split_path(path) {
+            let index = match class.position(name) {
+                Some(index) => index,
+                None => {
+                    let entries = class.entries.get_or_insert_with(Vec::new);
+                    entries.push((name.to_string(), ConfigEntry::ClassEntry(ConfigClass::new())));
+                    entries.len() - 1
+                }
+            };
+
+            class = match &mut class.entries.as_mut().unwrap()[index].1 {
+                ConfigEntry::ClassEntry(class) => class,
+                _ => return Err(error!("Config entry \"{}\" in \"{}\" is not a class.", name, path)),
+            };
+        }
+
+        Ok(class)
+    }
+
+    /// Sets the entry at `path`, and returns the entry it replaced. A replaced entry keeps its
+    /// position and the case of its name, and new entries are appended to their class. Missing
+    /// classes on the path are created.
+    pub fn set(&mut self, path: &str, entry: ConfigEntry) -> Result<Option<ConfigEntry>, Error> {
+        let (names, name) = split_last(path)?;
+        let class = self.class_or_insert(&names.join("/"))?;
+
+        match class.child_mut(name) {
+            Some(existing) => Ok(Some(std::mem::replace(existing, entry))),
+            None => {
+                class.entries.get_or_insert_with(Vec::new).push((name.to_string(), entry));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Removes the entry at `path`, and returns it.
+    pub fn remove(&mut self, path: &str) -> Option<ConfigEntry> {
+        let (names, name) = split_last(path).ok()?;
+        let class = self.class_at_mut(&names)?;
+        let index = class.position(name)?;
+
+        Some(class.entries.as_mut()?.remove(index).1)
+    }
+
+    /// Inserts an entry named `name` right before the entry at `path`, in the same class.
+    pub fn insert_before(&mut self, path: &str, name: &str, entry: ConfigEntry) -> Result<(), Error> {
+        self.insert_next_to(path, name, entry, 0)
+    }
+
+    /// Inserts an entry named `name` right after the entry at `path`, in the same class.
+    pub fn insert_after(&mut self, path: &str, name: &str, entry: ConfigEntry) -> Result<(), Error> {
+        self.insert_next_to(path, name, entry, 1)
+    }
+
+    fn insert_next_to(&mut self, path: &str, name: &str, entry: ConfigEntry, offset: usize) -> Result<(), Error> {
+        let (names, sibling) = split_last(path)?;
+        let class = self.class_at_mut(&names).ok_or_else(|| error!("Config entry \"{}\" not found.", path))?;
+
+        if class.position(name).is_some() {
+            return Err(error!("Config entry \"{}\" already exists next to \"{}\".", name, path));
+        }
+
+        let index = class.position(sibling).ok_or_else(|| error!("Config entry \"{}\" not found.", path))?;
+        class.entries.as_mut().unwrap().insert(index + offset, (name.to_string(), entry));
+
+        Ok(())
+    }
+
+    /// Merges another class into this one, like Arma applies a config on top of the ones loaded
+    /// before it:
+    ///
+    /// - Values replace existing entries in place, or are appended.
+    /// - Classes are merged recursively, and take the other class's parent, so a class that
+    ///   leaves out its parent no longer inherits. External class declarations (`class Foo;`)
+    ///   don't change existing classes.
+    /// - Class deletions (`delete Foo;`) remove the existing entry.
+    /// - Array expansions (`foo[] += {...}`) append their elements to the existing array.
+    pub fn merge(&mut self, other: ConfigClass) {
+        self.parent = other.parent;
+
+        let other_entries = match other.entries {
+            Some(entries) => entries,
+            None => return,
+        };
+
+        for (name, entry) in other_entries {
+            let index = self.position(&name);
+
+            match (index, entry) {
+                (Some(index), ConfigEntry::ClassEntry(ref class)) if class.is_deletion => {
+                    self.entries.as_mut().unwrap().remove(index);
+                },
+                (None, ConfigEntry::ClassEntry(ref class)) if class.is_deletion => {},
+                (Some(index), entry) => {
+                    let existing = &mut self.entries.as_mut().unwrap()[index].1;
+
+                    match (existing, entry) {
+                        (ConfigEntry::ClassEntry(_), ConfigEntry::ClassEntry(ref class)) if class.is_external => {},
+                        (ConfigEntry::ClassEntry(existing), ConfigEntry::ClassEntry(class)) => {
+                            if existing.entries.is_none() {
+                                existing.entries = Some(Vec::new());
+                                existing.is_external = false;
+                            }
+                            existing.merge(class);
+                        },
+                        (ConfigEntry::ArrayEntry(existing), ConfigEntry::ArrayEntry(array)) if array.is_expansion => {
+                            existing.elements.extend(array.elements);
+                        },
+                        (existing, entry) => *existing = entry,
+                    }
+                },
+                (None, entry) => {
+                    self.entries.get_or_insert_with(Vec::new).push((name, entry));
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,496 @@
+//! Serde support, for writing Rust types as configs and reading configs into Rust types
+//!
+//! Structs and maps are classes, sequences and tuples are arrays, and strings, integers, floats
+//! and booleans are values. `None` values are left out. Unit enum variants are strings.
+//!
+//! A class's parent is read from and written to the `$parent` field (see `PARENT_FIELD`). Config
+//! entry names can't contain `$`, so an ordinary entry is never mistaken for the parent:
+//!
+//! ```
+//! # use armake2::config::{to_config, from_config};
+//! # use serde::{Serialize, Deserialize};
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Soldier {
+//!     #[serde(rename = "$parent")]
+//!     parent: String,
+//!     #[serde(rename = "displayName")]
+//!     display_name: String,
+//!     weapons: Vec<String>,
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct CfgVehicles {
+//!     #[serde(rename = "B_Soldier_F")]
+//!     soldier: Soldier,
+//! }
+//!
+//! let vehicles = CfgVehicles {
+//!     soldier: Soldier {
+//!         parent: String::from("B_Soldier_base_F"),
+//!         display_name: String::from("The \"Best\" Rifleman"),
+//!         weapons: vec![String::from("arifle_MX_F")],
+//!     },
+//! };
+//!
+//! let config = to_config(&vehicles).unwrap();
+//! assert_eq!(concat!(
+//!     "class B_Soldier_F: B_Soldier_base_F {\n",
+//!     "    displayName = \"The \"\"Best\"\" Rifleman\";\n",
+//!     "    weapons[] = {\"arifle_MX_F\"};\n",
+//!     "};\n",
+//! ), config.to_string().unwrap());
+//!
+//! assert_eq!(vehicles, from_config(&config).unwrap());
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Error;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+use serde::Deserialize;
+
+use super::*;
+
+/// Field that holds the parent of a class, e.g. `#[serde(rename = "$parent")]`. It isn't a valid
+/// entry name, so it can't clash with one. An empty string is a class without a parent.
+pub const PARENT_FIELD: &str = "$parent";
+
+/// Serializes a struct or map to a config, with its fields as the root entries.
+pub fn to_config<T: Serialize + ?Sized>(value: &T) -> Result<Config, Error> {
+    match value.serialize(EntrySerializer).map_err(|e| error!("Failed to serialize config: {}", e))? {
+        Some(ConfigEntry::ClassEntry(class)) => Ok(Config::from_config_class(class)),
+        _ => Err(error!("Failed to serialize config: only structs and maps can be serialized as a config.")),
+    }
+}
+
+/// Deserializes a config's root entries into a struct or map.
+///
+/// Struct fields are matched to entries case-insensitively, like Arma does.
+pub fn from_config<'a, T: Deserialize<'a>>(config: &'a Config) -> Result<T, Error> {
+    from_config_class(config.inner())
+}
+
+/// Deserializes a class's entries into a struct or map.
+pub fn from_config_class<'a, T: Deserialize<'a>>(class: &'a ConfigClass) -> Result<T, Error> {
+    T::deserialize(Node::Class(class)).map_err(|e| error!("Failed to deserialize config: {}", e))
+}
+
+#[derive(Debug)]
+struct SerdeError(String);
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+/// Integers that don't fit in an int become 64-bit ints
+fn int_entry<T: Copy + fmt::Display>(value: T) -> Result<Option<ConfigEntry>, SerdeError> where i64: TryFrom<T> {
+    let value = i64::try_from(value)
+        .map_err(|_| SerdeError(format!("Integer {} doesn't fit in a 64-bit config int.", value)))?;
+
+    match i32::try_from(value) {
+        Ok(i) => Ok(Some(ConfigEntry::IntEntry(i))),
+        Err(_) => Ok(Some(ConfigEntry::Int64Entry(value))),
+    }
+}
+
+fn unsupported(kind: &str) -> SerdeError {
+    SerdeError(format!("{} can't be serialized to a config.", kind))
+}
+
+/// Serializes a value to a config entry, or `None` for values that are left out
+struct EntrySerializer;
+
+impl ser::Serializer for EntrySerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    type SerializeSeq = ArraySerializer;
+    type SerializeTuple = ArraySerializer;
+    type SerializeTupleStruct = ArraySerializer;
+    type SerializeTupleVariant = Impossible<Self::Ok, SerdeError>;
+    type SerializeMap = ClassSerializer;
+    type SerializeStruct = ClassSerializer;
+    type SerializeStructVariant = Impossible<Self::Ok, SerdeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::IntEntry(v as i32)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, SerdeError> { int_entry(v) }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::FloatEntry(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::FloatEntry(v as f32)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::StringEntry(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::StringEntry(v.to_string())))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, SerdeError> {
+        Err(unsupported("Bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, SerdeError> {
+        Err(unsupported("Unit"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, SerdeError> {
+        Err(unsupported(name))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Self::Ok, SerdeError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, name: &'static str, _index: u32, variant: &'static str, _value: &T) -> Result<Self::Ok, SerdeError> {
+        Err(unsupported(&format!("Enum variant {}::{}", name, variant)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArraySerializer, SerdeError> {
+        Ok(ArraySerializer { elements: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ArraySerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArraySerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        Err(unsupported(&format!("Enum variant {}::{}", name, variant)))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<ClassSerializer, SerdeError> {
+        Ok(ClassSerializer { class: ConfigClass::new(), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<ClassSerializer, SerdeError> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(self, name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, SerdeError> {
+        Err(unsupported(&format!("Enum variant {}::{}", name, variant)))
+    }
+}
+
+struct ArraySerializer {
+    elements: Vec<ConfigArrayElement>,
+}
+
+impl ser::SerializeSeq for ArraySerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let element = match value.serialize(EntrySerializer)? {
+            Some(ConfigEntry::StringEntry(s)) => ConfigArrayElement::StringElement(s),
+            Some(ConfigEntry::FloatEntry(f)) => ConfigArrayElement::FloatElement(f),
+            Some(ConfigEntry::IntEntry(i)) => ConfigArrayElement::IntElement(i),
+            Some(ConfigEntry::Int64Entry(i)) => ConfigArrayElement::Int64Element(i),
+            Some(ConfigEntry::ArrayEntry(a)) => ConfigArrayElement::ArrayElement(a),
+            Some(ConfigEntry::ClassEntry(_)) => return Err(SerdeError(String::from("Arrays can't contain classes."))),
+            None => return Err(SerdeError(String::from("Arrays can't contain empty values."))),
+        };
+
+        self.elements.push(element);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::ArrayEntry(ConfigArray::new(self.elements))))
+    }
+}
+
+impl ser::SerializeTuple for ArraySerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ArraySerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct ClassSerializer {
+    class: ConfigClass,
+    key: Option<String>,
+}
+
+impl ClassSerializer {
+    fn add_entry<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), SerdeError> {
+        let entry = value.serialize(EntrySerializer)?;
+
+        if key == PARENT_FIELD {
+            match entry {
+                Some(ConfigEntry::StringEntry(parent)) => self.class.parent = parent,
+                None => {},
+                _ => return Err(SerdeError(format!("{} must be a string.", PARENT_FIELD))),
+            }
+        } else if let Some(entry) = entry {
+            if self.class.get(&key).is_some() {
+                return Err(SerdeError(format!("Duplicate config entry \"{}\".", key)));
+            }
+
+            self.class.entries.get_or_insert_with(Vec::new).push((key, entry));
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for ClassSerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = match key.serialize(EntrySerializer)? {
+            Some(ConfigEntry::StringEntry(s)) => Some(s),
+            Some(ConfigEntry::IntEntry(i)) => Some(i.to_string()),
+            Some(ConfigEntry::Int64Entry(i)) => Some(i.to_string()),
+            _ => return Err(SerdeError(String::from("Config entry names must be strings."))),
+        };
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take().ok_or_else(|| SerdeError(String::from("Value without a name.")))?;
+        self.add_entry(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(ConfigEntry::ClassEntry(self.class)))
+    }
+}
+
+impl ser::SerializeStruct for ClassSerializer {
+    type Ok = Option<ConfigEntry>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.add_entry(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// A config value to deserialize from
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    String(&'a str),
+    Int(i64),
+    Float(f32),
+    Array(&'a [ConfigArrayElement]),
+    Class(&'a ConfigClass),
+}
+
+impl<'a> From<&'a ConfigEntry> for Node<'a> {
+    fn from(entry: &'a ConfigEntry) -> Self {
+        match entry {
+            ConfigEntry::StringEntry(s) => Node::String(s),
+            ConfigEntry::IntEntry(i) => Node::Int((*i).into()),
+            ConfigEntry::Int64Entry(i) => Node::Int(*i),
+            ConfigEntry::FloatEntry(f) => Node::Float(*f),
+            ConfigEntry::ArrayEntry(a) => Node::Array(&a.elements),
+            ConfigEntry::ClassEntry(c) => Node::Class(c),
+        }
+    }
+}
+
+impl<'a> From<&'a ConfigArrayElement> for Node<'a> {
+    fn from(element: &'a ConfigArrayElement) -> Self {
+        match element {
+            ConfigArrayElement::StringElement(s) => Node::String(s),
+            ConfigArrayElement::IntElement(i) => Node::Int((*i).into()),
+            ConfigArrayElement::Int64Element(i) => Node::Int(*i),
+            ConfigArrayElement::FloatElement(f) => Node::Float(*f),
+            ConfigArrayElement::ArrayElement(a) => Node::Array(&a.elements),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Node<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Node::String(s) => visitor.visit_borrowed_str(s),
+            Node::Int(i) => visitor.visit_i64(i),
+            Node::Float(f) => visitor.visit_f32(f),
+            Node::Array(elements) => visitor.visit_seq(ArrayAccess { elements: elements.iter() }),
+            Node::Class(class) => visitor.visit_map(ClassAccess::new(class, &[])),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Node::Int(i) => visitor.visit_bool(i != 0),
+            Node::String(s) if s.eq_ignore_ascii_case("true") => visitor.visit_bool(true),
+            Node::String(s) if s.eq_ignore_ascii_case("false") => visitor.visit_bool(false),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Node::Class(class) => visitor.visit_map(ClassAccess::new(class, fields)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            Node::String(s) => visitor.visit_enum(s.into_deserializer()),
+            _ => Err(de::Error::custom("Only unit enum variants can be deserialized from a config.")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct ArrayAccess<'a> {
+    elements: std::slice::Iter<'a, ConfigArrayElement>,
+}
+
+impl<'de> SeqAccess<'de> for ArrayAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        match self.elements.next() {
+            Some(element) => seed.deserialize(Node::from(element)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+/// Yields the parent of a class as `PARENT_FIELD`, followed by its entries. Entry names are
+/// replaced by the struct field they match case-insensitively.
+struct ClassAccess<'a> {
+    parent: Option<&'a str>,
+    entries: std::slice::Iter<'a, (String, ConfigEntry)>,
+    fields: &'static [&'static str],
+    value: Option<Node<'a>>,
+}
+
+impl<'a> ClassAccess<'a> {
+    fn new(class: &'a ConfigClass, fields: &'static [&'static str]) -> Self {
+        ClassAccess {
+            parent: Some(class.parent.as_str()).filter(|parent| !parent.is_empty()),
+            entries: class.entries().iter(),
+            fields,
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for ClassAccess<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        if let Some(parent) = self.parent.take() {
+            self.value = Some(Node::String(parent));
+            return seed.deserialize(PARENT_FIELD.into_deserializer()).map(Some);
+        }
+
+        match self.entries.next() {
+            Some((name, entry)) => {
+                self.value = Some(Node::from(entry));
+
+                let name: &'de str = match self.fields.iter().copied().find(|field| field.eq_ignore_ascii_case(name)) {
+                    Some(field) => field,
+                    None => name,
+                };
+
+                seed.deserialize(de::value::BorrowedStrDeserializer::new(name)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(de::Error::custom("Value without a name.")),
+        }
+    }
+}
@@ -83,13 +83,31 @@ impl<T> ConfigParseErrorExt<T> for Result<T, config_grammar::ParseError> {
                 };
 
                 let line = input.lines().nth(pe.line - 1).unwrap_or("");
+                let error = format_parse_error(line, file_origin, line_origin, pe.column, pe.expected);
 
-                Err(format_parse_error(line, file_origin, line_origin, pe.column, pe.expected))
+                match info.span_at(pe.line, pe.column) {
+                    Some(span) if !span.expansions.is_empty() => Err(error!("{}{}", error, format_expansions(span))),
+                    _ => Err(error)
+                }
             }
         }
     }
 }
 
+/// Notes on the macros that produced a span, innermost first, like a C compiler
+fn format_expansions(span: &SourceSpan) -> String {
+    let mut notes = String::from("\n");
+
+    for expansion in span.expansions.iter().rev() {
+        match expansion.definition {
+            Some(ref definition) => notes += &format!("\n  in expansion of {} defined at {}", expansion.name, definition),
+            None => notes += &format!("\n  in expansion of built-in {}", expansion.name)
+        }
+    }
+
+    notes + &format!("\n  used at {}", span.origin)
+}
+
 fn format_parse_error(line: &str, file: String, line_number: usize, column_number: usize, expected: HashSet<&'static str>) -> Error {
     let trimmed = line.trim_start();
     let expected_list: Vec<String> = expected.iter().cloned().map(|x| format!("{:?}", x)).collect();
@@ -5,11 +5,17 @@ use std::collections::HashMap;
 use std::env::current_dir;
 use std::fs::{File, read_dir};
 use std::io::{Read, Write, Error};
+use std::fmt;
 use std::iter::{Sum};
+use std::ops::Range;
 use std::path::{Path, PathBuf, Component};
 
 use crate::error::*;
 
+mod builtins;
+use builtins::Builtins;
+pub use builtins::SOURCE_DATE_EPOCH;
+
 pub mod preprocess_grammar {
     #![allow(missing_docs)]
     include!(concat!(env!("OUT_DIR"), "/preprocess_grammar.rs"));
@@ -21,7 +27,8 @@ pub struct Definition {
     name: String,
     parameters: Option<Vec<String>>,
     value: Vec<Token>,
-    local: bool
+    local: bool,
+    location: Option<SourceLocation>
 }
 
 /// Preprocessor directive
@@ -37,6 +44,10 @@ pub enum Directive {
     IfDefDirective(String),
     /// `#ifndef` directive containing the name of the macro
     IfNDefDirective(String),
+    /// `#if` directive containing the unevaluated condition
+    IfDirective(String),
+    /// `#elif` directive containing the unevaluated condition
+    ElIfDirective(String),
     /// `#else` directive
     ElseDirective,
     /// `#endif` directive
@@ -64,16 +75,61 @@ pub enum Token {
     /// Comment token containing a number of newlines
     CommentToken(u32),
     /// Token for the concatenation operator (`##`)
-    ConcatToken
+    ConcatToken,
+    /// Start of the tokens produced by a macro, for the source map
+    ExpansionStart(MacroExpansion),
+    /// End of the tokens produced by a macro
+    ExpansionEnd
 }
 
 /// Preprocessor line
 #[derive(Debug)]
 pub enum Line {
-    /// Directive line
-    DirectiveLine(Directive, u32),
-    /// Non-directive line of tokens
-    TokenLine(Vec<Token>),
+    /// Directive line, with the number of newlines in trailing comments and the offset of the
+    /// line in the input
+    DirectiveLine(Directive, u32, usize),
+    /// Non-directive line of tokens, with the offset of each token in the input
+    TokenLine(Vec<Token>, Vec<usize>),
+}
+
+/// Position in a preprocessed file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The file, or `None` for the original input to `preprocess` if `origin` was not given
+    pub file: Option<PathBuf>,
+    /// Line number, starting at 1
+    pub line: u32,
+    /// Column in bytes, starting at 1
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref path) => write!(f, "{}:{}:{}", path.display(), self.line, self.column),
+            None => write!(f, "<input>:{}:{}", self.line, self.column)
+        }
+    }
+}
+
+/// Macro expansion that produced part of the output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MacroExpansion {
+    /// Name of the macro
+    pub name: String,
+    /// Where the macro was defined, or `None` for built-in macros
+    pub definition: Option<SourceLocation>,
+}
+
+/// Part of an output line, and where it came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// Byte range of the span in the output line
+    pub columns: Range<usize>,
+    /// Where the text was found, or for expanded macros, where the outermost macro was used
+    pub origin: SourceLocation,
+    /// Macros that were expanded to produce the text, from outermost to innermost
+    pub expansions: Vec<MacroExpansion>,
 }
 
 /// Struct for additional information about preprocessor output. Contains import stack used for
@@ -84,7 +140,77 @@ pub struct PreprocessInfo {
     /// `PathBuf` to the file where the line was found. The path may be `None` if the line was in the
     /// original input to `preprocess` and `origin` was not given.
     pub line_origins: Vec<(u32, Option<PathBuf>)>,
-    import_stack: Vec<PathBuf>
+    /// For every line in the output, the spans of the line with their origin and the macros that
+    /// produced them.
+    pub source_map: Vec<Vec<SourceSpan>>,
+    import_stack: Vec<PathBuf>,
+    builtins: Builtins
+}
+
+impl PreprocessInfo {
+    /// The span of the output at a line and column (both starting at 1), or the closest span
+    /// before it on the same line.
+    pub fn span_at(&self, line: usize, column: usize) -> Option<&SourceSpan> {
+        let spans = self.source_map.get(line.checked_sub(1)?)?;
+        let offset = column.saturating_sub(1);
+
+        spans.iter().find(|span| span.columns.contains(&offset))
+            .or_else(|| spans.iter().rev().find(|span| span.columns.start <= offset))
+    }
+}
+
+/// Byte offsets of the start of every line in `input`
+fn line_starts(input: &str) -> Vec<usize> {
+    std::iter::once(0).chain(input.match_indices('\n').map(|(i, _)| i + 1)).collect()
+}
+
+fn source_location(origin: &Option<PathBuf>, line_starts: &[usize], offset: usize) -> SourceLocation {
+    let line = match line_starts.binary_search(&offset) {
+        Ok(line) => line,
+        Err(line) => line - 1
+    };
+
+    SourceLocation {
+        file: origin.clone(),
+        line: line as u32 + 1,
+        column: (offset - line_starts[line]) as u32 + 1,
+    }
+}
+
+/// Adds the spans produced by the resolved tokens of one input token to the source map of a line
+fn map_spans(tokens: &[Token], origin: &SourceLocation, column: &mut usize, spans: &mut Vec<SourceSpan>) {
+    let mut expansions: Vec<MacroExpansion> = Vec::new();
+
+    for token in tokens {
+        match token {
+            Token::ExpansionStart(expansion) => expansions.push(expansion.clone()),
+            Token::ExpansionEnd => { expansions.pop(); },
+            _ => {
+                let (text, _) = Token::concat(std::slice::from_ref(token));
+                let length = text.replace("\r\n", "\n").replace("\\\n", "").len();
+
+                if length == 0 {
+                    continue;
+                }
+
+                let start = *column;
+                *column += length;
+
+                if let Some(last) = spans.last_mut() {
+                    if last.columns.end == start && &last.origin == origin && last.expansions == expansions {
+                        last.columns.end = *column;
+                        continue;
+                    }
+                }
+
+                spans.push(SourceSpan {
+                    columns: start..*column,
+                    origin: origin.clone(),
+                    expansions: expansions.clone(),
+                });
+            }
+        }
+    }
 }
 
 fn parse_macro(input: &str) -> Macro {
@@ -115,12 +241,14 @@ impl Clone for Token {
             Token::MacroToken(m) => Token::MacroToken(m.clone()),
             Token::CommentToken(n) => Token::CommentToken(*n),
             Token::ConcatToken => Token::ConcatToken,
+            Token::ExpansionStart(e) => Token::ExpansionStart(e.clone()),
+            Token::ExpansionEnd => Token::ExpansionEnd,
         }
     }
 }
 
 impl Definition {
-    fn value(&self, arguments: &Option<Vec<String>>, def_map: &HashMap<String,Definition>, stack: &[Definition]) -> Result<Option<Vec<Token>>, Error> {
+    fn value(&self, arguments: &Option<Vec<String>>, def_map: &HashMap<String,Definition>, stack: &[Definition], builtins: &mut Builtins) -> Result<Option<Vec<Token>>, Error> {
         let params = self.parameters.clone().unwrap_or_default();
         let args = arguments.clone().unwrap_or_default();
 
@@ -148,19 +276,20 @@ impl Definition {
             for (param, arg) in params.iter().zip(args.iter()) {
                 let mut tokens = preprocess_grammar::tokens(&arg).expect("Failed to parse macro argument");
                 let stack: Vec<Definition> = Vec::new();
-                tokens = Macro::resolve_all(&tokens, &def_map, &stack).expect("Failed to resolve macro arguments");
+                tokens = Macro::resolve_all(&tokens, &def_map, &stack, builtins)?;
 
                 local_map.insert(param.clone(), Definition {
                     name: param.clone(),
                     parameters: None,
                     value: tokens,
-                    local: true
+                    local: true,
+                    location: None
                 });
             }
 
-            tokens = Macro::resolve_all(&tokens, &local_map, &stack_new)?;
+            tokens = Macro::resolve_all(&tokens, &local_map, &stack_new, builtins)?;
         } else {
-            tokens = Macro::resolve_all(&tokens, &def_map, &stack_new)?;
+            tokens = Macro::resolve_all(&tokens, &def_map, &stack_new, builtins)?;
         }
 
         Ok(Some(tokens))
@@ -168,7 +297,7 @@ impl Definition {
 }
 
 impl Macro {
-    fn resolve_pseudoargs(&self, def_map: &HashMap<String, Definition>, stack: &[Definition]) -> Result<Vec<Token>, Error> {
+    fn resolve_pseudoargs(&self, def_map: &HashMap<String, Definition>, stack: &[Definition], builtins: &mut Builtins) -> Result<Vec<Token>, Error> {
         let mut tokens: Vec<Token> = Vec::new();
         tokens.push(Token::RegularToken(self.name.clone()));
 
@@ -179,7 +308,7 @@ impl Macro {
         let (_, without_name) = self.original.split_at(self.name.len());
         let mut arg_tokens = preprocess_grammar::tokens(&without_name).expect("Failed to parse macro arguments.");
 
-        arg_tokens = Macro::resolve_all(&arg_tokens, &def_map, &stack)?;
+        arg_tokens = Macro::resolve_all(&arg_tokens, &def_map, &stack, builtins)?;
         for t in arg_tokens {
             tokens.push(t);
         }
@@ -187,39 +316,91 @@ impl Macro {
         Ok(tokens)
     }
 
-    fn resolve(&self, def_map: &HashMap<String, Definition>, stack: &[Definition]) -> Result<Vec<Token>, Error> {
+    /// Expands a built-in macro like `__LINE__` or `__EVAL(...)`, or returns `None` if this isn't
+    /// one. The expressions of `__EVAL` and `__EXEC` have their macros expanded first.
+    fn resolve_builtin(&self, def_map: &HashMap<String, Definition>, stack: &[Definition], builtins: &mut Builtins) -> Result<Option<Vec<Token>>, Error> {
+        let expression = if Builtins::takes_expression(&self.name) {
+            let start = self.original.find(&self.name).unwrap_or(0) + self.name.len();
+            let arguments = self.original[start..].trim();
+
+            if !arguments.starts_with('(') || !arguments.ends_with(')') {
+                return Err(error!("{} needs an expression in parentheses.", self.name));
+            }
+
+            let tokens = preprocess_grammar::tokens(&arguments[1..arguments.len() - 1])
+                .map_err(|e| error!("Failed to parse {} expression: {}", self.name, e))?;
+            let resolved = Macro::resolve_all(&tokens, def_map, stack, builtins)?;
+
+            Some(Token::concat(&resolved).0)
+        } else {
+            None
+        };
+
+        let value = match builtins.expand(&self.name, expression.as_deref())? {
+            Some(value) => value,
+            None => return Ok(None)
+        };
+
+        let value = if self.quoted { format!("\"{}\"", value) } else { value };
+
+        Ok(Some(vec![
+            Token::ExpansionStart(MacroExpansion { name: self.name.clone(), definition: None }),
+            Token::RegularToken(value),
+            Token::ExpansionEnd,
+        ]))
+    }
+
+    fn resolve(&self, def_map: &HashMap<String, Definition>, stack: &[Definition], builtins: &mut Builtins) -> Result<Vec<Token>, Error> {
         match def_map.get(&self.name) {
             Some(def) => {
-                let value = def.value(&self.arguments, def_map, stack)?;
+                let value = def.value(&self.arguments, def_map, stack, builtins)?;
 
                 if !def.local && self.quoted {
                     // @todo: complain
                 }
 
                 if let Some(tokens) = value {
-                    if self.quoted {
+                    let tokens = if self.quoted {
                         let (concatted, newlines) = Token::concat(&tokens);
                         let mut tokens: Vec<Token> = Vec::new();
                         tokens.push(Token::NewlineToken(format!("\"{}\"", concatted.trim()), newlines));
-                        Ok(tokens)
+                        tokens
                     } else {
-                        Ok(tokens)
+                        tokens
+                    };
+
+                    // Macro parameters are part of the macro they're used in
+                    if def.local {
+                        return Ok(tokens);
                     }
+
+                    let mut expanded = Vec::with_capacity(tokens.len() + 2);
+                    expanded.push(Token::ExpansionStart(MacroExpansion {
+                        name: self.name.clone(),
+                        definition: def.location.clone(),
+                    }));
+                    expanded.extend(tokens);
+                    expanded.push(Token::ExpansionEnd);
+
+                    Ok(expanded)
                 } else {
-                    self.resolve_pseudoargs(def_map, stack)
+                    self.resolve_pseudoargs(def_map, stack, builtins)
                 }
             },
-            None => self.resolve_pseudoargs(def_map, stack)
+            None => match self.resolve_builtin(def_map, stack, builtins)? {
+                Some(tokens) => Ok(tokens),
+                None => self.resolve_pseudoargs(def_map, stack, builtins)
+            }
         }
     }
 
-    fn resolve_all(tokens: &[Token], def_map: &HashMap<String, Definition>, stack: &[Definition]) -> Result<Vec<Token>, Error> {
+    fn resolve_all(tokens: &[Token], def_map: &HashMap<String, Definition>, stack: &[Definition], builtins: &mut Builtins) -> Result<Vec<Token>, Error> {
         let mut result: Vec<Token> = Vec::new();
 
         for token in tokens {
             match token {
                 Token::MacroToken(ref m) => {
-                    let resolved = m.resolve(def_map, stack)?;
+                    let resolved = m.resolve(def_map, stack, builtins)?;
                     for t in resolved {
                         result.push(t);
                     }
@@ -383,16 +564,298 @@ fn find_include_file(include_path: &str, origin: Option<&PathBuf>, search_paths:
     }
 }
 
+/// Token of an `#if` condition, after macros have been expanded
+#[derive(Clone, Debug, PartialEq)]
+enum ConditionToken {
+    Number(i64),
+    Operator(&'static str),
+}
+
+/// Operators in `#if` conditions, with longer operators first so they match before their prefixes
+const CONDITION_OPERATORS: [&str; 24] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<<", ">>",
+    "+", "-", "*", "/", "%", "<", ">", "!", "~", "&", "|", "^", "(", ")", "?", ":",
+];
+
+/// Evaluates the integer expressions of `#if` and `#elif` directives, like a C preprocessor.
+/// Identifiers left over after macro expansion evaluate to 0.
+struct ConditionParser {
+    tokens: Vec<ConditionToken>,
+    position: usize,
+}
+
+impl ConditionParser {
+    fn evaluate(input: &str) -> Result<i64, Error> {
+        let mut parser = ConditionParser {
+            tokens: ConditionParser::tokenize(input)?,
+            position: 0,
+        };
+
+        let value = parser.ternary()?;
+
+        match parser.tokens.get(parser.position) {
+            None => Ok(value),
+            Some(token) => Err(error!("Unexpected {:?} in condition \"{}\".", token, input.trim())),
+        }
+    }
+
+    fn tokenize(input: &str) -> Result<Vec<ConditionToken>, Error> {
+        let mut tokens = Vec::new();
+        let mut rest = input;
+
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or_else(|| rest.len());
+                let word = &rest[..end];
+                rest = &rest[end..];
+
+                if c.is_ascii_digit() {
+                    let digits = word.trim_end_matches(|c: char| c == 'u' || c == 'U' || c == 'l' || c == 'L');
+                    let number = if digits.starts_with("0x") || digits.starts_with("0X") {
+                        i64::from_str_radix(&digits[2..], 16)
+                    } else {
+                        digits.parse()
+                    };
+
+                    tokens.push(ConditionToken::Number(number.map_err(|_| error!("Invalid number \"{}\" in condition.", word))?));
+                } else {
+                    tokens.push(ConditionToken::Number(0));
+                }
+            } else {
+                match CONDITION_OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => {
+                        tokens.push(ConditionToken::Operator(*op));
+                        rest = &rest[op.len()..];
+                    },
+                    None => return Err(error!("Unexpected \"{}\" in condition \"{}\".", c, input.trim()))
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn eat(&mut self, operator: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(ConditionToken::Operator(op)) if *op == operator => {
+                self.position += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64, Error> {
+        let condition = self.binary(1)?;
+
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+
+        let if_true = self.ternary()?;
+
+        if !self.eat(":") {
+            return Err(error!("Expected \":\" in conditional expression."));
+        }
+
+        let if_false = self.ternary()?;
+
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, Error> {
+        let mut left = self.unary()?;
+
+        while let Some(ConditionToken::Operator(operator)) = self.tokens.get(self.position).cloned() {
+            let precedence = match binary_precedence(operator) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => break
+            };
+
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+
+            left = match operator {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                ">=" => (left >= right) as i64,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err(error!("Division by zero in condition.")),
+                "/" => left.wrapping_div(right),
+                "%" => left.wrapping_rem(right),
+                _ => unreachable!()
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, Error> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        match token {
+            Some(ConditionToken::Number(n)) => Ok(n),
+            Some(ConditionToken::Operator("!")) => Ok((self.unary()? == 0) as i64),
+            Some(ConditionToken::Operator("~")) => Ok(!self.unary()?),
+            Some(ConditionToken::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(ConditionToken::Operator("+")) => self.unary(),
+            Some(ConditionToken::Operator("(")) => {
+                let value = self.ternary()?;
+
+                if self.eat(")") {
+                    Ok(value)
+                } else {
+                    Err(error!("Expected \")\" in condition."))
+                }
+            },
+            Some(token) => Err(error!("Unexpected {:?} in condition.", token)),
+            None => Err(error!("Unexpected end of condition."))
+        }
+    }
+}
+
+fn binary_precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | "<=" | ">" | ">=" => Some(7),
+        "<<" | ">>" => Some(8),
+        "+" | "-" => Some(9),
+        "*" | "/" | "%" => Some(10),
+        _ => None
+    }
+}
+
+/// Replaces `defined(NAME)`, `defined NAME` and `__has_include("path")` in a condition with 1 or
+/// 0. This happens before macro expansion, so the names aren't expanded themselves.
+fn replace_condition_builtins(condition: &str, definition_map: &HashMap<String, Definition>, origin: Option<&PathBuf>, includefolders: &[PathBuf]) -> Result<String, Error> {
+    let mut output = String::new();
+    let mut rest = condition;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let end = rest[1..].find('"').map(|i| i + 2).unwrap_or_else(|| rest.len());
+            output += &rest[..end];
+            rest = &rest[end..];
+            continue;
+        }
+
+        if !(c.is_ascii_alphanumeric() || c == '_') {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or_else(|| rest.len());
+        let word = &rest[..end];
+        rest = &rest[end..];
+
+        match word {
+            "defined" => {
+                let arguments = rest.trim_start();
+                let parenthesized = arguments.starts_with('(');
+                let arguments = if parenthesized { arguments[1..].trim_start() } else { arguments };
+
+                let name_end = arguments.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or_else(|| arguments.len());
+                if name_end == 0 {
+                    return Err(error!("Expected a macro name after \"defined\"."));
+                }
+
+                let name = &arguments[..name_end];
+                rest = &arguments[name_end..];
+
+                if parenthesized {
+                    rest = rest.trim_start();
+                    if !rest.starts_with(')') {
+                        return Err(error!("Expected \")\" after \"defined({}\".", name));
+                    }
+                    rest = &rest[1..];
+                }
+
+                output += if definition_map.contains_key(name) { " 1 " } else { " 0 " };
+            },
+            "__has_include" => {
+                let arguments = rest.trim_start();
+                if !arguments.starts_with('(') {
+                    return Err(error!("Expected \"(\" after \"__has_include\"."));
+                }
+
+                let arguments = arguments[1..].trim_start();
+                let close = match arguments.chars().next() {
+                    Some('"') => '"',
+                    Some('<') => '>',
+                    _ => return Err(error!("Expected a quoted path in \"__has_include\"."))
+                };
+
+                let path_end = arguments[1..].find(close).ok_or_else(|| error!("Unterminated path in \"__has_include\"."))? + 1;
+                let path = &arguments[1..path_end];
+                rest = arguments[path_end + 1..].trim_start();
+
+                if !rest.starts_with(')') {
+                    return Err(error!("Expected \")\" after \"__has_include({}\".", path));
+                }
+                rest = &rest[1..];
+
+                let found = !path.is_empty() && find_include_file(path, origin, includefolders).is_ok();
+                output += if found { " 1 " } else { " 0 " };
+            },
+            _ => output += word
+        }
+    }
+
+    Ok(output)
+}
+
+/// Evaluates the condition of an `#if` or `#elif` directive
+fn evaluate_condition(condition: &str, definition_map: &HashMap<String, Definition>, origin: Option<&PathBuf>, includefolders: &[PathBuf], builtins: &mut Builtins) -> Result<bool, Error> {
+    let condition = condition.replace("\\\r\n", " ").replace("\\\n", " ");
+    let replaced = replace_condition_builtins(&condition, definition_map, origin, includefolders)?;
+
+    let tokens = preprocess_grammar::tokens(&replaced).map_err(|e| error!("Failed to parse condition \"{}\": {}", condition.trim(), e))?;
+    let stack: Vec<Definition> = Vec::new();
+    let resolved = Macro::resolve_all(&tokens, definition_map, &stack, builtins)?;
+    let (expanded, _) = Token::concat(&resolved);
+
+    let value = ConditionParser::evaluate(&expanded).prepend_error(format!("Failed to evaluate condition \"{}\":", condition.trim()))?;
+
+    Ok(value != 0)
+}
+
 fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut HashMap<String, Definition>, info: &mut PreprocessInfo, includefolders: &[PathBuf]) -> Result<String, Error> {
     let lines = preprocess_grammar::file(&input).format_error(&origin, &input)?;
     let mut output = String::from("");
     let mut original_lineno = 1;
     let mut level = 0;
     let mut level_true = 0;
+    // Whether a branch of each open conditional has been taken, so `#elif` and `#else` are skipped
+    let mut branch_taken: Vec<bool> = Vec::new();
+    let line_starts = line_starts(&input);
 
     for line in lines {
+        info.builtins.file = origin.clone();
+        info.builtins.line = original_lineno;
+
         match line {
-            Line::DirectiveLine(dir, newlines) => {
+            Line::DirectiveLine(dir, newlines, offset) => {
                 original_lineno += newlines;
 
                 match dir {
@@ -417,7 +880,7 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
 
                         output += &result;
                     },
-                    Directive::DefineDirective(def) => {
+                    Directive::DefineDirective(mut def) => {
                         original_lineno += u32::sum(def.value.iter().map(|t| match t {
                             Token::NewlineToken(_s, n) => *n,
                             Token::CommentToken(n) => *n,
@@ -430,6 +893,7 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                             // @todo: warn about redefine
                         }
 
+                        def.location = Some(source_location(&origin, &line_starts, offset));
                         definition_map.insert(def.name.clone(), def);
                     }
                     Directive::UndefDirective(name) => {
@@ -438,16 +902,43 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                         definition_map.remove(&name);
                     }
                     Directive::IfDefDirective(name) => {
-                        level_true += if level_true == level && definition_map.contains_key(&name) { 1 } else { 0 };
+                        let taken = level_true == level && definition_map.contains_key(&name);
+                        level_true += if taken { 1 } else { 0 };
                         level += 1;
+                        branch_taken.push(taken);
                     }
                     Directive::IfNDefDirective(name) => {
-                        level_true += if level_true == level && !definition_map.contains_key(&name) { 1 } else { 0 };
+                        let taken = level_true == level && !definition_map.contains_key(&name);
+                        level_true += if taken { 1 } else { 0 };
                         level += 1;
+                        branch_taken.push(taken);
+                    }
+                    Directive::IfDirective(condition) => {
+                        original_lineno += condition.matches('\n').count() as u32;
+
+                        let taken = level_true == level && evaluate_condition(&condition, definition_map, origin.as_ref(), includefolders, &mut info.builtins)?;
+                        level_true += if taken { 1 } else { 0 };
+                        level += 1;
+                        branch_taken.push(taken);
+                    }
+                    Directive::ElIfDirective(condition) => {
+                        original_lineno += condition.matches('\n').count() as u32;
+
+                        let taken = branch_taken.last_mut().ok_or_else(|| error!("#elif without #if"))?;
+
+                        if level_true == level {
+                            level_true -= 1;
+                        } else if level_true + 1 == level && !*taken && evaluate_condition(&condition, definition_map, origin.as_ref(), includefolders, &mut info.builtins)? {
+                            level_true = level;
+                            *taken = true;
+                        }
                     }
                     Directive::ElseDirective => {
-                        if level_true + 1 == level {
+                        let taken = branch_taken.last_mut().ok_or_else(|| error!("#else without #if"))?;
+
+                        if level_true + 1 == level && !*taken {
                             level_true = level;
+                            *taken = true;
                         } else if level_true == level {
                             level_true -= 1;
                         }
@@ -458,12 +949,29 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                         if level_true > level {
                             level_true -= 1;
                         }
+                        branch_taken.pop();
                     }
                 }
             },
-            Line::TokenLine(tokens) => {
+            Line::TokenLine(tokens, offsets) => {
+                // Skipped lines aren't resolved, so built-ins like `__EXEC` have no effect
+                if level > level_true {
+                    original_lineno += Token::concat(&tokens).1;
+                    continue;
+                }
+
                 let stack: Vec<Definition> = Vec::new();
-                let resolved = Macro::resolve_all(&tokens, &definition_map, &stack).prepend_error("Failed to resolve macros:")?;
+                let mut resolved: Vec<Token> = Vec::new();
+                let mut spans: Vec<SourceSpan> = Vec::new();
+                let mut column = 0;
+
+                // Tokens are resolved one at a time, to map the output back to them
+                for (token, offset) in tokens.iter().zip(offsets) {
+                    let token_resolved = Macro::resolve_all(std::slice::from_ref(token), &definition_map, &stack, &mut info.builtins).prepend_error("Failed to resolve macros:")?;
+
+                    map_spans(&token_resolved, &source_location(&origin, &line_starts, offset), &mut column, &mut spans);
+                    resolved.extend(token_resolved);
+                }
 
                 let (mut result, newlines) = Token::concat(&resolved);
                 result = result.replace("\r\n", "\n");
@@ -472,12 +980,11 @@ fn preprocess_rec(input: String, origin: Option<PathBuf>, definition_map: &mut H
                 let before = result.len();
                 result = result.replace("\\\n", "");
 
-                if level > level_true { continue; }
-
                 output += &result;
                 output += "\n";
 
                 info.line_origins.push((original_lineno, origin.clone()));
+                info.source_map.push(spans);
                 original_lineno += (before - result.len()) as u32 / 2;
             }
         }
@@ -520,7 +1027,9 @@ pub fn preprocess(mut input: String, origin: Option<PathBuf>, includefolders: &[
 
     let mut info = PreprocessInfo {
         line_origins: Vec::new(),
-        import_stack: Vec::new()
+        source_map: Vec::new(),
+        import_stack: Vec::new(),
+        builtins: Builtins::new()?
     };
 
     if let Some(ref path) = origin {
@@ -529,6 +1038,16 @@ pub fn preprocess(mut input: String, origin: Option<PathBuf>, includefolders: &[
 
     let mut def_map: HashMap<String, Definition> = HashMap::new();
 
+    for name in builtins::PREDEFINED.iter() {
+        def_map.insert(name.to_string(), Definition {
+            name: name.to_string(),
+            parameters: None,
+            value: vec![Token::RegularToken(String::from("1"))],
+            local: false,
+            location: None
+        });
+    }
+
     match preprocess_rec(input, origin, &mut def_map, &mut info, includefolders) {
         Ok(result) => Ok((result, info)),
         Err(e) => Err(e)
@@ -0,0 +1,441 @@
+//! Built-in macros of Arma's preprocessor, like `__LINE__`, `__COUNTER__` and `__EVAL`
+
+use std::collections::HashMap;
+use std::env;
+use std::io::Error;
+use std::path::PathBuf;
+
+/// Environment variable with a Unix timestamp that date and time macros use instead of the
+/// current time, for reproducible builds. Times are then in UTC, regardless of the time zone.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Macros that are always defined, so they can be checked with `#ifdef`
+pub const PREDEFINED: [&str; 2] = ["__ARMA__", "__ARMA3__"];
+
+/// State of the built-in macros while preprocessing a file: the current position, `__COUNTER__`
+/// and the variables set by `__EXEC`.
+#[derive(Debug)]
+pub struct Builtins {
+    /// File being preprocessed, for `__FILE__`
+    pub file: Option<PathBuf>,
+    /// Line being preprocessed, for `__LINE__`
+    pub line: u32,
+    counter: u32,
+    variables: HashMap<String, Value>,
+    local_time: time::Tm,
+    utc_time: time::Tm,
+}
+
+impl Builtins {
+    /// Creates the built-in state, with the time from `SOURCE_DATE_EPOCH` if it is set
+    pub fn new() -> Result<Builtins, Error> {
+        let (local_time, utc_time) = match env::var(SOURCE_DATE_EPOCH) {
+            Ok(epoch) => {
+                let seconds: i64 = epoch.trim().parse()
+                    .map_err(|_| error!("{} must be a Unix timestamp, not \"{}\".", SOURCE_DATE_EPOCH, epoch))?;
+                let time = time::at_utc(time::Timespec::new(seconds, 0));
+                (time, time)
+            },
+            Err(_) => (time::now(), time::now_utc())
+        };
+
+        Ok(Builtins {
+            file: None,
+            line: 1,
+            counter: 0,
+            variables: HashMap::new(),
+            local_time,
+            utc_time,
+        })
+    }
+
+    /// Whether `name` is a built-in macro that takes an expression argument
+    pub fn takes_expression(name: &str) -> bool {
+        name == "__EVAL" || name == "__EXEC"
+    }
+
+    /// Expands a built-in macro, or returns `None` if `name` isn't one. `expression` is the
+    /// macro-expanded argument of `__EVAL` and `__EXEC`.
+    pub fn expand(&mut self, name: &str, expression: Option<&str>) -> Result<Option<String>, Error> {
+        let local = &self.local_time;
+        let utc = &self.utc_time;
+
+        let value = match name {
+            "__LINE__" => self.line.to_string(),
+            "__FILE__" => {
+                let file = self.file.as_ref().map(|path| path.to_string_lossy().to_string()).unwrap_or_default();
+                quote(&file)
+            },
+            "__COUNTER__" => {
+                let counter = self.counter;
+                self.counter += 1;
+                counter.to_string()
+            },
+            "__COUNTER_RESET__" => {
+                self.counter = 0;
+                String::new()
+            },
+            "__DATE_ARR__" => format!("{},{},{},{},{},{}",
+                local.tm_year + 1900, local.tm_mon + 1, local.tm_mday, local.tm_hour, local.tm_min, local.tm_sec),
+            "__DATE_STR__" => format!("\"{}/{:02}/{:02}, {:02}:{:02}:{:02}\"",
+                local.tm_year + 1900, local.tm_mon + 1, local.tm_mday, local.tm_hour, local.tm_min, local.tm_sec),
+            "__DATE_STR_ISO8601__" => format!("\"{}-{:02}-{:02}T{:02}:{:02}:{:02}Z\"",
+                utc.tm_year + 1900, utc.tm_mon + 1, utc.tm_mday, utc.tm_hour, utc.tm_min, utc.tm_sec),
+            "__TIME__" => format!("{:02}:{:02}:{:02}", local.tm_hour, local.tm_min, local.tm_sec),
+            "__TIME_UTC__" => format!("{:02}:{:02}:{:02}", utc.tm_hour, utc.tm_min, utc.tm_sec),
+            "__DAY__" => local.tm_mday.to_string(),
+            "__MONTH__" => (local.tm_mon + 1).to_string(),
+            "__YEAR__" => (local.tm_year + 1900).to_string(),
+            "__TIMESTAMP_UTC__" => utc.to_timespec().sec.to_string(),
+            "__EVAL" => {
+                let expression = expression.ok_or_else(|| error!("__EVAL needs an expression."))?;
+
+                match self.execute(expression).map_err(|e| error!("Failed to evaluate \"__EVAL({})\": {}", expression.trim(), e))? {
+                    Some(value) => value.to_string(),
+                    None => String::new()
+                }
+            },
+            "__EXEC" => {
+                let expression = expression.ok_or_else(|| error!("__EXEC needs an expression."))?;
+
+                self.execute(expression).map_err(|e| error!("Failed to execute \"__EXEC({})\": {}", expression.trim(), e))?;
+                String::new()
+            },
+            _ => return Ok(None)
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Runs `;` separated statements, and returns the value of the last one
+    fn execute(&mut self, input: &str) -> Result<Option<Value>, Error> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            variables: &mut self.variables,
+        };
+
+        parser.statements()
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Value of an `__EVAL` or `__EXEC` expression
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn number(&self) -> Result<f64, Error> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            other => Err(error!("Expected a number, got {}.", other))
+        }
+    }
+
+    fn bool(&self) -> Result<bool, Error> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            other => Err(error!("Expected a boolean, got {}.", other))
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            // Whole numbers are written without a fraction, so they can be used as integers
+            Value::Number(n) if n.fract() == 0. && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", quote(s)),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ExpressionToken {
+    Number(f64),
+    String(String),
+    Identifier(String),
+    Operator(&'static str),
+}
+
+/// Operators, with longer operators first so they match before their prefixes
+const OPERATORS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||",
+    "+", "-", "*", "/", "%", "^", "<", ">", "!", "(", ")", "=", ";",
+];
+
+fn tokenize(input: &str) -> Result<Vec<ExpressionToken>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            // Strings are quoted with " or ', and the quote is escaped by doubling it
+            chars.next();
+            let mut value = String::new();
+
+            loop {
+                match chars.next() {
+                    Some((_, q)) if q == c => {
+                        if chars.peek().map(|&(_, next)| next) == Some(c) {
+                            chars.next();
+                            value.push(c);
+                        } else {
+                            break;
+                        }
+                    },
+                    Some((_, other)) => value.push(other),
+                    None => return Err(error!("Unterminated string."))
+                }
+            }
+
+            tokens.push(ExpressionToken::String(value));
+        } else if c.is_ascii_digit() || c == '.' || c == '$' {
+            let end = input[start..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '$'))
+                .map(|i| start + i)
+                .unwrap_or_else(|| input.len());
+            let mut literal = &input[start..end];
+
+            // Exponents like 1e-5 continue past the sign
+            if literal.ends_with(|c: char| c == 'e' || c == 'E') && !literal.starts_with("0x") && !literal.starts_with('$') {
+                if let Some(sign) = input[end..].chars().next().filter(|c| *c == '-' || *c == '+') {
+                    let exponent_end = input[end + 1..].find(|c: char| !c.is_ascii_digit()).map(|i| end + 1 + i).unwrap_or_else(|| input.len());
+                    if exponent_end > end + sign.len_utf8() {
+                        literal = &input[start..exponent_end];
+                    }
+                }
+            }
+
+            let number = if literal.starts_with("0x") || literal.starts_with("0X") {
+                i64::from_str_radix(&literal[2..], 16).map(|n| n as f64).ok()
+            } else if literal.starts_with('$') {
+                i64::from_str_radix(&literal[1..], 16).map(|n| n as f64).ok()
+            } else {
+                literal.parse().ok()
+            };
+
+            tokens.push(ExpressionToken::Number(number.ok_or_else(|| error!("Invalid number \"{}\".", literal))?));
+
+            while chars.peek().map(|&(i, _)| i < start + literal.len()).unwrap_or(false) {
+                chars.next();
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = input[start..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map(|i| start + i)
+                .unwrap_or_else(|| input.len());
+
+            // SQF names are case-insensitive
+            tokens.push(ExpressionToken::Identifier(input[start..end].to_lowercase()));
+
+            while chars.peek().map(|&(i, _)| i < end).unwrap_or(false) {
+                chars.next();
+            }
+        } else {
+            match OPERATORS.iter().find(|op| input[start..].starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(ExpressionToken::Operator(*op));
+                    for _ in 0..op.len() {
+                        chars.next();
+                    }
+                },
+                None => return Err(error!("Unexpected \"{}\".", c))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser and evaluator for the SQF subset commonly used in `__EVAL` and
+/// `__EXEC`: numbers, strings, booleans, variables, arithmetic, comparisons, logic and a few
+/// math commands.
+struct Parser<'a> {
+    tokens: Vec<ExpressionToken>,
+    position: usize,
+    variables: &'a mut HashMap<String, Value>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&ExpressionToken> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, operator: &str) -> bool {
+        match self.peek() {
+            Some(ExpressionToken::Operator(op)) if *op == operator => {
+                self.position += 1;
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn statements(&mut self) -> Result<Option<Value>, Error> {
+        let mut value = None;
+
+        while self.peek().is_some() {
+            if self.eat(";") {
+                continue;
+            }
+
+            value = self.statement()?;
+
+            if self.peek().is_some() && !self.eat(";") {
+                return Err(error!("Expected \";\", got {:?}.", self.peek().unwrap()));
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn statement(&mut self) -> Result<Option<Value>, Error> {
+        if let (Some(ExpressionToken::Identifier(name)), Some(ExpressionToken::Operator("="))) =
+            (self.tokens.get(self.position).cloned(), self.tokens.get(self.position + 1).cloned())
+        {
+            self.position += 2;
+            let value = self.expression()?;
+            self.variables.insert(name, value);
+            return Ok(None);
+        }
+
+        self.expression().map(Some)
+    }
+
+    fn expression(&mut self) -> Result<Value, Error> {
+        self.binary(1)
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Value, Error> {
+        let mut left = self.unary()?;
+
+        while let Some(ExpressionToken::Operator(operator)) = self.peek().cloned() {
+            let precedence = match binary_precedence(operator) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => break
+            };
+
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+
+            left = apply(operator, left, right)?;
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Value, Error> {
+        let token = self.peek().cloned();
+        self.position += 1;
+
+        match token {
+            Some(ExpressionToken::Number(n)) => Ok(Value::Number(n)),
+            Some(ExpressionToken::String(s)) => Ok(Value::String(s)),
+            Some(ExpressionToken::Operator("-")) => Ok(Value::Number(-self.unary()?.number()?)),
+            Some(ExpressionToken::Operator("+")) => Ok(Value::Number(self.unary()?.number()?)),
+            Some(ExpressionToken::Operator("!")) => Ok(Value::Bool(!self.unary()?.bool()?)),
+            Some(ExpressionToken::Operator("(")) => {
+                let value = self.expression()?;
+
+                if self.eat(")") {
+                    Ok(value)
+                } else {
+                    Err(error!("Expected \")\"."))
+                }
+            },
+            Some(ExpressionToken::Identifier(name)) => self.identifier(&name),
+            Some(token) => Err(error!("Unexpected {:?}.", token)),
+            None => Err(error!("Unexpected end of expression."))
+        }
+    }
+
+    fn identifier(&mut self, name: &str) -> Result<Value, Error> {
+        let math: Option<fn(f64) -> f64> = match name {
+            "abs" => Some(f64::abs),
+            "ceil" => Some(f64::ceil),
+            "floor" => Some(f64::floor),
+            "round" => Some(f64::round),
+            "sqrt" => Some(f64::sqrt),
+            "exp" => Some(f64::exp),
+            "ln" => Some(f64::ln),
+            "log" => Some(f64::log10),
+            // SQF trigonometry works in degrees
+            "sin" => Some(|n: f64| n.to_radians().sin()),
+            "cos" => Some(|n: f64| n.to_radians().cos()),
+            "tan" => Some(|n: f64| n.to_radians().tan()),
+            _ => None
+        };
+
+        if let Some(math) = math {
+            return Ok(Value::Number(math(self.unary()?.number()?)));
+        }
+
+        match name {
+            "str" => {
+                let value = self.unary()?;
+                Ok(Value::String(match value {
+                    Value::String(s) => quote(&s),
+                    other => other.to_string(),
+                }))
+            },
+            "pi" => Ok(Value::Number(std::f64::consts::PI)),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => self.variables.get(name).cloned().ok_or_else(|| error!("Undefined variable \"{}\".", name))
+        }
+    }
+}
+
+fn binary_precedence(operator: &str) -> Option<u8> {
+    match operator {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => Some(3),
+        "+" | "-" => Some(4),
+        "*" | "/" | "%" => Some(5),
+        "^" => Some(6),
+        _ => None
+    }
+}
+
+fn apply(operator: &str, left: Value, right: Value) -> Result<Value, Error> {
+    Ok(match (operator, left, right) {
+        ("+", Value::String(a), Value::String(b)) => Value::String(a + &b),
+        ("==", Value::String(a), Value::String(b)) => Value::Bool(a.eq_ignore_ascii_case(&b)),
+        ("!=", Value::String(a), Value::String(b)) => Value::Bool(!a.eq_ignore_ascii_case(&b)),
+        ("==", Value::Bool(a), Value::Bool(b)) => Value::Bool(a == b),
+        ("!=", Value::Bool(a), Value::Bool(b)) => Value::Bool(a != b),
+        ("&&", a, b) => Value::Bool(a.bool()? && b.bool()?),
+        ("||", a, b) => Value::Bool(a.bool()? || b.bool()?),
+        ("/", _, Value::Number(b)) | ("%", _, Value::Number(b)) if b == 0. => return Err(error!("Division by zero.")),
+        (operator, a, b) => {
+            let (a, b) = (a.number()?, b.number()?);
+
+            match operator {
+                "+" => Value::Number(a + b),
+                "-" => Value::Number(a - b),
+                "*" => Value::Number(a * b),
+                "/" => Value::Number(a / b),
+                "%" => Value::Number(a % b),
+                "^" => Value::Number(a.powf(b)),
+                "==" => Value::Bool(a == b),
+                "!=" => Value::Bool(a != b),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => unreachable!()
+            }
+        }
+    })
+}
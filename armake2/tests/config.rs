@@ -1,6 +1,8 @@
+use std::collections::BTreeMap;
 use std::io::{Cursor, Seek, SeekFrom};
 
 use armake2::config::*;
+use serde::{Deserialize, Serialize};
 
 #[test]
 fn config_read() {
@@ -46,3 +48,561 @@ class CfgPatches {
     };
 };", output.trim());
 }
+
+#[test]
+fn config_error_in_macro() {
+    let input = String::from("\
+#define BROKEN(x) x[] = ;
+class A {
+    BROKEN(foo)
+};
+");
+
+    let error = Config::from_string(input, None, &Vec::new()).unwrap_err().to_string();
+
+    assert!(error.contains("in expansion of BROKEN defined at <input>:1:1"), "{}", error);
+    assert!(error.contains("used at <input>:3:5"), "{}", error);
+}
+
+#[test]
+fn config_path_api() {
+    let input = String::from("\
+class Mission {
+    class Entities {
+        items = 1;
+        class Item0 {
+            position[] = {1, 2, 3};
+        };
+    };
+};
+version = 54;
+");
+
+    let mut config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    match config.get("mission/entities/ITEM0/Position") {
+        Some(ConfigEntry::ArrayEntry(array)) => assert_eq!(3, array.elements.len()),
+        other => panic!("Unexpected entry: {:?}", other),
+    }
+    assert!(config.get("Mission/Entities/items/foo").is_none());
+    assert!(config.get("Mission/Missing").is_none());
+
+    // Replaced entries keep their position and name, new ones are appended
+    let old = config.set("Mission/Entities/ITEMS", ConfigEntry::IntEntry(2)).unwrap();
+    assert!(matches!(old, Some(ConfigEntry::IntEntry(1))));
+    assert!(config.set("Mission/Intel/year", ConfigEntry::IntEntry(2035)).unwrap().is_none());
+    assert!(config.set("version/foo", ConfigEntry::IntEntry(1)).is_err());
+
+    config.inner_mut().insert_before("Mission/Entities/Item0", "Item_Before", ConfigEntry::ClassEntry(ConfigClass::new())).unwrap();
+    config.inner_mut().insert_after("version", "author", ConfigEntry::StringEntry(String::from("Nou"))).unwrap();
+    assert!(config.inner_mut().insert_after("version", "AUTHOR", ConfigEntry::IntEntry(1)).is_err());
+    assert!(config.inner_mut().insert_after("missing", "foo", ConfigEntry::IntEntry(1)).is_err());
+
+    assert!(matches!(config.remove("mission/entities/item0"), Some(ConfigEntry::ClassEntry(_))));
+    assert!(config.remove("mission/entities/item0").is_none());
+
+    assert_eq!("\
+class Mission {
+    class Entities {
+        items = 2;
+        class Item_Before {};
+    };
+    class Intel {
+        year = 2035;
+    };
+};
+version = 54;
+author = \"Nou\";
+", config.to_string().unwrap());
+}
+
+#[test]
+fn config_merge() {
+    let base = String::from("\
+class CfgVehicles {
+    class Man;
+    class Soldier: Man {
+        armor = 2;
+        weapons[] = {\"Rifle\"};
+        class Turrets {};
+        class HitPoints {
+            class HitHead {};
+        };
+    };
+    class Civilian: Man {};
+    class Pilot: Soldier {
+        armor = 1;
+    };
+};
+");
+
+    let patch = String::from("\
+class CfgVehicles {
+    class Man;
+    class Soldier: Man {
+        weapons[] += {\"Pistol\"};
+        armor = 4;
+        delete Turrets;
+        class HitPoints {
+            class HitBody {};
+        };
+    };
+    class Civilian;
+    class Pilot {
+        armor = 3;
+    };
+    delete Missing;
+    class Officer: Soldier {};
+};
+");
+
+    let mut config = Config::from_string(base, None, &Vec::new()).unwrap();
+    config.merge(Config::from_string(patch, None, &Vec::new()).unwrap());
+
+    assert_eq!("\
+class CfgVehicles {
+    class Man;
+    class Soldier: Man {
+        armor = 4;
+        weapons[] = {\"Rifle\", \"Pistol\"};
+        class HitPoints {
+            class HitHead {};
+            class HitBody {};
+        };
+    };
+    class Civilian: Man {};
+    class Pilot {
+        armor = 3;
+    };
+    class Officer: Soldier {};
+};
+", config.to_string().unwrap());
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+struct Addon {
+    CfgPatches: BTreeMap<String, Patch>,
+    CfgMusic: Music,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Patch {
+    name: String,
+    units: Vec<String>,
+    #[serde(rename = "requiredVersion")]
+    required_version: f32,
+    #[serde(rename = "requiredAddons")]
+    required_addons: Vec<String>,
+    #[serde(rename = "skipWhenMissingDependencies")]
+    skip_when_missing_dependencies: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Music {
+    tracks: Vec<String>,
+    #[serde(flatten)]
+    classes: BTreeMap<String, Track>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Track {
+    #[serde(rename = "$parent", default, skip_serializing_if = "String::is_empty")]
+    parent: String,
+    name: String,
+    sound: (String, f32, i32),
+    duration: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<Theme>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+enum Theme {
+    action,
+    calm,
+}
+
+#[test]
+fn config_serde() {
+    let mut patches = BTreeMap::new();
+    patches.insert(String::from("LAAT_Music"), Patch {
+        name: String::from("The \"Best\" Music"),
+        units: Vec::new(),
+        required_version: 0.1,
+        required_addons: vec![String::from("A3_Data_F")],
+        skip_when_missing_dependencies: true,
+    });
+
+    let mut classes = BTreeMap::new();
+    classes.insert(String::from("LAAT_Intro"), Track {
+        parent: String::new(),
+        name: String::from("Intro"),
+        sound: (String::from("\\LAAT\\Music\\intro.ogg"), 1.5, 1),
+        duration: 95,
+        theme: Some(Theme::calm),
+    });
+    classes.insert(String::from("LAAT_Outro"), Track {
+        parent: String::from("LAAT_Intro"),
+        name: String::from("Outro"),
+        sound: (String::from("\\LAAT\\Music\\outro.ogg"), 1.0, 1),
+        duration: 120,
+        theme: None,
+    });
+
+    let addon = Addon {
+        CfgPatches: patches,
+        CfgMusic: Music {
+            tracks: vec![String::from("LAAT_Intro"), String::from("LAAT_Outro")],
+            classes,
+        },
+    };
+
+    let config = to_config(&addon).unwrap();
+
+    assert_eq!("\
+class CfgPatches {
+    class LAAT_Music {
+        name = \"The \"\"Best\"\" Music\";
+        units[] = {};
+        requiredVersion = 0.1;
+        requiredAddons[] = {\"A3_Data_F\"};
+        skipWhenMissingDependencies = 1;
+    };
+};
+class CfgMusic {
+    tracks[] = {\"LAAT_Intro\", \"LAAT_Outro\"};
+    class LAAT_Intro {
+        name = \"Intro\";
+        sound[] = {\"\\LAAT\\Music\\intro.ogg\", 1.5, 1};
+        duration = 95;
+        theme = \"calm\";
+    };
+    class LAAT_Outro: LAAT_Intro {
+        name = \"Outro\";
+        sound[] = {\"\\LAAT\\Music\\outro.ogg\", 1.0, 1};
+        duration = 120;
+    };
+};
+", config.to_string().unwrap());
+
+    // Round trip through the parser, with names in a different case
+    let input = config.to_string().unwrap().replace("requiredVersion", "REQUIREDVERSION");
+    let parsed = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    assert_eq!(addon, from_config(&parsed).unwrap());
+
+    let track: Track = from_config_class(parsed.class("CfgMusic/LAAT_Outro").unwrap()).unwrap();
+    assert_eq!("LAAT_Intro", track.parent);
+    assert_eq!(None, track.theme);
+}
+
+#[test]
+fn config_serde_parent() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Class {
+        #[serde(rename = "$parent", default)]
+        base: String,
+        #[serde(rename = "__parent")]
+        legacy: String,
+        parent: String,
+    }
+
+    let input = String::from("class Foo: Bar { __PARENT = \"Baz\"; parent = \"Qux\"; };");
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+    let class: Class = from_config_class(config.class("Foo").unwrap()).unwrap();
+
+    assert_eq!(Class {
+        base: String::from("Bar"),
+        legacy: String::from("Baz"),
+        parent: String::from("Qux"),
+    }, class);
+
+    let mut classes = BTreeMap::new();
+    classes.insert(String::from("Foo"), class);
+
+    assert_eq!("\
+class Foo: Bar {
+    __parent = \"Baz\";
+    parent = \"Qux\";
+};
+", to_config(&classes).unwrap().to_string().unwrap());
+}
+
+#[test]
+fn config_serde_errors() {
+    #[derive(Serialize)]
+    struct Nested {
+        values: Vec<Patch>,
+    }
+
+    #[derive(Serialize)]
+    struct Large {
+        value: u64,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Version {
+        #[serde(rename = "requiredVersion")]
+        _required_version: f32,
+    }
+
+    assert!(to_config(&Nested { values: vec![Patch {
+        name: String::new(),
+        units: Vec::new(),
+        required_version: 0.1,
+        required_addons: Vec::new(),
+        skip_when_missing_dependencies: false,
+    }] }).is_err());
+    assert!(to_config(&Large { value: u64::max_value() }).is_err());
+    assert!(to_config(&vec![1, 2, 3]).is_err());
+
+    let config = Config::from_string(String::from("requiredVersion = \"new\";"), None, &Vec::new()).unwrap();
+    assert!(from_config::<Version>(&config).is_err());
+}
+
+#[test]
+fn config_database() {
+    let base = String::from("\
+class CfgVehicles {
+    class Land;
+    class Car: Land {
+        armor = 20;
+        hiddenSelections[] = {\"camo1\"};
+        class Turrets {
+            class MainTurret {
+                gunnerName = \"Gunner\";
+            };
+        };
+    };
+    class Truck: Car {
+        armor = 40;
+        hiddenSelections[] += {\"camo2\"};
+    };
+    class Bike: Car {};
+};
+");
+
+    let addon = String::from("\
+class CfgVehicles {
+    class Car;
+    class Truck;
+    class MyTruck: Truck {
+        hiddenSelections[] += {\"camo3\"};
+        class Turrets: Turrets {
+            class MainTurret: MainTurret {
+                gunnerName = \"Commander\";
+            };
+        };
+    };
+    class MyCar: car {};
+    class Broken: Missing {};
+    delete Bike;
+};
+");
+
+    let mut database = ConfigDatabase::new();
+    database.add(Config::from_string(base, None, &Vec::new()).unwrap());
+
+    assert_eq!(vec!["CfgVehicles/Truck", "CfgVehicles/Bike"], database.subclasses("cfgvehicles/car"));
+
+    database.add(Config::from_string(addon, None, &Vec::new()).unwrap());
+
+    match database.get("CfgVehicles/MyTruck/hiddenSelections") {
+        Some(ConfigEntry::ArrayEntry(array)) => assert_eq!(3, array.elements.len()),
+        _ => panic!("Missing hiddenSelections[]"),
+    }
+
+    match database.get("CfgVehicles/MyTruck/armor") {
+        Some(ConfigEntry::IntEntry(armor)) => assert_eq!(40, armor),
+        _ => panic!("Missing armor"),
+    }
+
+    match database.get("CfgVehicles/MyCar/Turrets/MainTurret/gunnerName") {
+        Some(ConfigEntry::StringEntry(name)) => assert_eq!("Gunner", name),
+        _ => panic!("Missing gunnerName"),
+    }
+
+    assert_eq!(Some(String::from("CfgVehicles/Car/Turrets/MainTurret")),
+        database.parent("CfgVehicles/MyTruck/Turrets/MainTurret"));
+    assert_eq!(Some(String::from("CfgVehicles/Car/Turrets")), database.class_path("CfgVehicles/MyCar/turrets"));
+    assert_eq!(vec!["CfgVehicles/Truck", "CfgVehicles/Car"], database.ancestors("CfgVehicles/MyTruck"));
+    assert!(database.inherits_from("CfgVehicles/MyTruck/Turrets", "CfgVehicles/Car/Turrets"));
+    assert!(!database.inherits_from("CfgVehicles/Car", "CfgVehicles/MyCar"));
+
+    assert_eq!(vec!["CfgVehicles/Truck", "CfgVehicles/MyTruck", "CfgVehicles/MyCar"], database.subclasses("CfgVehicles/Car"));
+    assert_eq!(vec!["CfgVehicles/MyTruck/Turrets/MainTurret"],
+        database.subclasses("CfgVehicles/Car/Turrets/MainTurret"));
+
+    assert_eq!(vec![
+        MissingParent { class: String::from("CfgVehicles/Car"), parent: String::from("Land") },
+        MissingParent { class: String::from("CfgVehicles/Broken"), parent: String::from("Missing") },
+    ], database.missing_parents());
+}
+
+#[test]
+fn config_rapify_entry_types() {
+    let input = String::from("\
+class A;
+delete B;
+x = 4294967296;
+y[] += {1};
+");
+
+    let config = Config::from_string(input.clone(), None, &Vec::new()).unwrap();
+
+    match config.get("x") {
+        Some(ConfigEntry::Int64Entry(x)) => assert_eq!(4294967296, *x),
+        _ => panic!("Missing int64 entry"),
+    }
+
+    match config.get("y") {
+        Some(ConfigEntry::ArrayEntry(array)) => assert!(array.is_expansion),
+        _ => panic!("Missing array expansion"),
+    }
+
+    let mut rapified = Vec::new();
+    config.write_rapified(&mut rapified).unwrap();
+
+    assert_eq!(&b"\0raP\0\0\0\0\x08\0\0\0\x31\0\0\0\
+\0\x04\
+\x03A\0\
+\x04B\0\
+\x01\x06x\0\0\0\0\0\x01\0\0\0\
+\x05\x01\0\0\0y\0\x01\x02\x01\0\0\0\
+\0\0\0\0"[..], &rapified[..]);
+
+    let config = Config::read_rapified(&mut Cursor::new(rapified)).unwrap();
+    assert_eq!(input, config.to_string().unwrap());
+}
+
+#[test]
+fn config_integer_overflow() {
+    let input = String::from("\
+x = 99999999999999999999;
+y[] = {-99999999999999999999, 9223372036854775807};
+z = 0xFFFFFFFFFFFFFFFFFF;
+");
+
+    let config = Config::from_string(input, None, &Vec::new()).unwrap();
+
+    match config.get("x") {
+        Some(ConfigEntry::FloatEntry(x)) => assert_eq!(1e20, *x),
+        _ => panic!("Missing float entry"),
+    }
+
+    match config.get("y") {
+        Some(ConfigEntry::ArrayEntry(array)) => match &array.elements[..] {
+            [ConfigArrayElement::FloatElement(min), ConfigArrayElement::Int64Element(max)] => {
+                assert_eq!(-1e20, *min);
+                assert_eq!(i64::max_value(), *max);
+            },
+            elements => panic!("Unexpected elements {:?}", elements),
+        },
+        _ => panic!("Missing array"),
+    }
+
+    // Hexadecimal literals can't be floats, so they are kept as unquoted strings
+    match config.get("z") {
+        Some(ConfigEntry::StringEntry(z)) => assert_eq!("0xFFFFFFFFFFFFFFFFFF", z),
+        _ => panic!("Missing string entry"),
+    }
+}
+
+#[test]
+fn config_rapify_round_trip() {
+    // Laid out like a derapified vehicle addon, with every entry type
+    let input = String::from("\
+class CfgPatches {
+    class rhs_c_cars {
+        units[] = {\"rhs_uaz_vdv\", \"rhs_uaz_open_vdv\"};
+        weapons[] = {};
+        requiredVersion = 1.98;
+        requiredAddons[] = {\"A3_Soft_F\", \"rhs_main\"};
+        version = \"0.5.6\";
+        versionAr[] = {0, 5, 6, 0};
+        timestamp = 1617184824171;
+    };
+};
+class CfgVehicles {
+    class Car;
+    class Car_F: Car {
+        class Turrets;
+        class HitPoints {
+            class HitLFWheel;
+        };
+    };
+    delete rhs_uaz_base_old;
+    class rhs_uaz_base: Car_F {
+        scope = 0;
+        displayName = \"UAZ-469 \"\"Kozlik\"\"\";
+        hiddenSelections[] = {\"camo1\", \"camo2\"};
+        hiddenSelectionsTextures[] += {\"rhsafrf\\addons\\rhs_a2port_car\\uaz\\data\\uaz_main_co.paa\"};
+        armor = 40;
+        fuelCapacity = 55.5;
+        rpm[] = {{0, 1200}, {-0.5, 3500.25}, {-2147483648, 2147483647}};
+        class Turrets: Turrets {};
+        class HitPoints: HitPoints {
+            class HitLFWheel: HitLFWheel {
+                armor = 0.3;
+                radius = 0.33;
+            };
+        };
+        class EventHandlers {
+            init = \"(_this select 0) setVariable [\"\"rhs_decalNumber\"\", -1];\";
+        };
+    };
+};
+");
+
+    let config = Config::from_string(input.clone(), None, &Vec::new()).unwrap();
+
+    let mut rapified = Vec::new();
+    config.write_rapified(&mut rapified).unwrap();
+
+    let derapified = Config::read_rapified(&mut Cursor::new(&rapified)).unwrap();
+    assert_eq!(input, derapified.to_string().unwrap());
+
+    let mut rerapified = Vec::new();
+    derapified.write_rapified(&mut rerapified).unwrap();
+    assert_eq!(rapified, rerapified);
+}
+
+#[test]
+fn config_rapified_fixture() {
+    // Laid out byte by byte from the raP format, not by Config::write_rapified
+    let fixture = include_bytes!("config.bin");
+
+    let config = Config::read_rapified(&mut Cursor::new(&fixture[..])).unwrap();
+
+    assert_eq!("\
+class CfgPatches {
+    class laat_fixture {
+        units[] = {};
+        requiredAddons[] = {\"A3_Data_F\"};
+        requiredVersion = 2.0;
+        timestamp = 1634567890123;
+    };
+};
+class CfgVehicles {
+    class Car;
+    class LAAT_Car: Car {
+        displayName = \"LAAT \"\"Car\"\"\";
+        armor = 40;
+        ids[] = {4294967296, -1, 0.5, \"a\", {2}};
+        hiddenSelections[] += {\"camo\"};
+        class Turrets;
+    };
+    delete LAAT_Old;
+};
+", config.to_string().unwrap());
+
+    match config.get("CfgPatches/laat_fixture/timestamp") {
+        Some(ConfigEntry::Int64Entry(timestamp)) => assert_eq!(1634567890123, *timestamp),
+        _ => panic!("Missing int64 entry"),
+    }
+
+    let mut rapified = Vec::new();
+    config.write_rapified(&mut rapified).unwrap();
+    assert_eq!(&fixture[..], &rapified[..]);
+}
@@ -65,6 +65,145 @@ fn test_preprocess_ifdef() {
     assert_eq!("abc = 1234;", output.trim());
 }
 
+#[test]
+fn test_preprocess_if() {
+    let input = String::from("\
+#define MAJOR 3
+#define MINOR 14
+#define VERSION (MAJOR * 100 + MINOR)
+
+#if VERSION >= 314 && defined(MAJOR) && !defined UNKNOWN
+    a = 1;
+#elif MAJOR == 3
+    a = 2;
+#else
+    a = 3;
+#endif
+
+#if UNKNOWN
+    b = 1;
+#elif (MINOR % 4) == 2 ? 0x10 : 0 // trailing comment
+    b = 2;
+#elif 1
+    b = 3;
+#endif
+
+#if 0
+    #if 1 / 0
+    #endif
+    c = 1;
+#else
+    c = 2;
+#endif
+
+#if(MAJOR == 2)
+    d = 1;
+#elif(MINOR == 14)
+    d = 2;
+#endif
+");
+
+    let (output, _) = preprocess(input, None, &Vec::new()).unwrap();
+    let output: Vec<&str> = output.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+
+    assert_eq!(vec!["a = 1;", "b = 2;", "c = 2;", "d = 2;"], output);
+}
+
+#[test]
+fn test_preprocess_if_errors() {
+    for condition in ["1 +", "(1", "1 / 0", "1 $ 2", "defined("].iter() {
+        let input = format!("#if {}\nfoo\n#endif\n", condition);
+        assert!(preprocess(input, None, &Vec::new()).is_err(), "{}", condition);
+    }
+
+    assert!(preprocess(String::from("#elif 1\n"), None, &Vec::new()).is_err());
+}
+
+#[test]
+fn test_preprocess_has_include() {
+    let includedir = tempdir().unwrap();
+
+    let addondir = includedir.path().join("whatever");
+    create_dir(&addondir).unwrap();
+
+    File::create(addondir.join("script_macros.hpp")).unwrap().write_all(b"#define FOO 1\n").unwrap();
+    File::create(addondir.join("$PBOPREFIX$")).unwrap().write_all(b"\\x\\cba\\addons\\whatever\n").unwrap();
+
+    let input = String::from("\
+#if __has_include(\"\\x\\cba\\addons\\whatever\\script_macros.hpp\")
+    #include \"\\x\\cba\\addons\\whatever\\script_macros.hpp\"
+#endif
+#if __has_include(\"\\x\\ace\\addons\\main\\script_macros.hpp\")
+    ace = 1;
+#endif
+foo = FOO;\n");
+
+    let includefolders = vec![PathBuf::from(includedir.path())];
+    let (output, _) = preprocess(input, Some(PathBuf::from("myfile")), &includefolders).unwrap();
+
+    assert_eq!("foo = 1;", output.trim());
+}
+
+#[test]
+fn test_preprocess_builtins() {
+    std::env::set_var(SOURCE_DATE_EPOCH, "1700000000");
+
+    let input = String::from("\
+#define DOUBLE(x) __EVAL((x) * 2)
+line = __LINE__;
+file = __FILE__;
+__EXEC(a = 3; b = \"foo\")
+a = __EVAL(a * 2 + 1);
+b = __EVAL(b + \"bar\");
+c = DOUBLE(21);
+d = __EVAL(10 / 4);
+e = __EVAL(round 2.6);
+f = __EVAL(a > 2 && !false);
+counter[] = {__COUNTER__, __COUNTER__};
+__COUNTER_RESET__
+reset = __COUNTER__;
+date[] = {__DATE_ARR__};
+iso = __DATE_STR_ISO8601__;
+stamp = __TIMESTAMP_UTC__;
+#ifdef __ARMA3__
+arma = 1;
+#endif
+#if 0
+__EXEC(a = 0)
+#endif
+g = __EVAL(a);
+");
+
+    let (output, _) = preprocess(input, Some(PathBuf::from("myfile")), &Vec::new()).unwrap();
+    let output: Vec<&str> = output.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+
+    assert_eq!(vec![
+        "line = 2;",
+        "file = \"myfile\";",
+        "a = 7;",
+        "b = \"foobar\";",
+        "c = 42;",
+        "d = 2.5;",
+        "e = 3;",
+        "f = true;",
+        "counter[] = {0, 1};",
+        "reset = 0;",
+        "date[] = {2023,11,14,22,13,20};",
+        "iso = \"2023-11-14T22:13:20Z\";",
+        "stamp = 1700000000;",
+        "arma = 1;",
+        "g = 3;",
+    ], output);
+}
+
+#[test]
+fn test_preprocess_builtin_errors() {
+    for expression in ["__EVAL(undefined)", "__EVAL(1 / 0)", "__EVAL(1 +)", "__EVAL(\"a\" - 1)", "__EXEC(a = )"].iter() {
+        let input = format!("foo = {};\n", expression);
+        assert!(preprocess(input, None, &Vec::new()).is_err(), "{}", expression);
+    }
+}
+
 #[test]
 fn test_preprocess_include() {
     let input = String::from("\
@@ -122,3 +261,36 @@ class test\\
     assert_eq!(5, info.line_origins.len());
     assert_eq!(8, info.line_origins[2].0);
 }
+
+#[test]
+fn test_preprocess_source_map() {
+    let input = String::from("\
+#define INNER(x) x = 1
+#define OUTER INNER(foo)
+class A {
+    OUTER;
+    bar = 2;
+};
+");
+
+    let (output, info) = preprocess(input, Some(PathBuf::from("myfile")), &Vec::new()).unwrap();
+
+    assert_eq!(Some("foo = 1;"), output.lines().nth(1));
+    assert_eq!(output.lines().count(), info.source_map.len());
+
+    let spans = &info.source_map[1];
+    assert_eq!(2, spans.len());
+
+    assert_eq!(0..7, spans[0].columns);
+    assert_eq!(SourceLocation { file: Some(PathBuf::from("myfile")), line: 4, column: 5 }, spans[0].origin);
+    assert_eq!(vec!["OUTER", "INNER"], spans[0].expansions.iter().map(|e| e.name.as_str()).collect::<Vec<_>>());
+    assert_eq!(Some(2), spans[0].expansions[0].definition.as_ref().map(|d| d.line));
+    assert_eq!(Some(1), spans[0].expansions[1].definition.as_ref().map(|d| d.line));
+
+    assert_eq!(7..8, spans[1].columns);
+    assert!(spans[1].expansions.is_empty());
+
+    assert_eq!(Some(&spans[0]), info.span_at(2, 3));
+    assert_eq!(Some(&spans[1]), info.span_at(2, 20));
+    assert!(info.source_map[2][0].expansions.is_empty());
+}
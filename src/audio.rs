@@ -0,0 +1,406 @@
+//! Audio decoding, loudness measurement and Ogg Vorbis encoding, for music in formats Arma can't
+//! play or at inconsistent volumes.
+//!
+//! Decoding uses symphonia. Encoding uses vorbis_rs, which compiles the bundled libvorbis with a C
+//! compiler, so it's only available with the `transcode` feature.
+
+use crate::Result;
+#[cfg(feature = "transcode")]
+use std::convert::TryFrom;
+#[cfg(feature = "transcode")]
+use std::num::{NonZeroU32, NonZeroU8};
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::{Hint, ProbeResult};
+#[cfg(feature = "transcode")]
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+/// Extensions of audio files that are transcoded to Ogg Vorbis
+pub const TRANSCODED_EXTENSIONS: &[&str] = &["wav", "mp3", "flac"];
+
+/// Decoded audio, as one buffer of samples per channel
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl DecodedAudio {
+    /// Length of the audio in whole seconds
+    pub fn duration(&self) -> usize {
+        let frames = self.channels.first().map(Vec::len).unwrap_or_default();
+
+        frames / self.sample_rate.max(1) as usize
+    }
+
+    /// Highest absolute sample value
+    pub fn peak(&self) -> f32 {
+        self.channels
+            .iter()
+            .flatten()
+            .fold(0., |peak: f32, sample| peak.max(sample.abs()))
+    }
+
+    /// Change the volume by `gain` decibels
+    pub fn apply_gain(&mut self, gain: f32) {
+        let factor = 10f32.powf(gain / 20.);
+
+        for sample in self.channels.iter_mut().flatten() {
+            *sample *= factor;
+        }
+    }
+
+    /// Integrated loudness in LUFS, following ITU-R BS.1770 (K-weighting with absolute and
+    /// relative gating), or `None` if the audio is silent. All channels are weighted equally.
+    pub fn loudness(&self) -> Option<f32> {
+        let rate = self.sample_rate as f64;
+        let frames = self.channels.first().map(Vec::len).unwrap_or_default();
+
+        // 400ms blocks, overlapping by 75%
+        let block = (rate * 0.4) as usize;
+        let step = (rate * 0.1) as usize;
+
+        if block == 0 || frames < block {
+            return None;
+        }
+
+        // Sum of K-weighted squared samples for every 100ms step, per channel
+        let steps = frames / step;
+        let mut energy = vec![0f64; steps];
+
+        for channel in self.channels.iter() {
+            let mut filter = KWeighting::new(rate);
+
+            for (index, sample) in channel.iter().take(steps * step).enumerate() {
+                let weighted = filter.process(*sample as f64);
+                energy[index / step] += weighted * weighted;
+            }
+        }
+
+        let steps_per_block = block / step;
+        let blocks: Vec<f64> = energy
+            .windows(steps_per_block)
+            .map(|window| window.iter().sum::<f64>() / (steps_per_block * step) as f64)
+            .collect();
+
+        let loudness = |mean_square: f64| -0.691 + 10. * mean_square.log10();
+        let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+        // Absolute gate at -70 LUFS
+        let gated: Vec<f64> = blocks.into_iter().filter(|z| loudness(*z) > -70.).collect();
+        if gated.is_empty() {
+            return None;
+        }
+
+        // Relative gate 10 LU below the absolute-gated loudness
+        let threshold = loudness(mean(&gated)) - 10.;
+        let gated: Vec<f64> = gated.into_iter().filter(|z| loudness(*z) > threshold).collect();
+
+        Some(loudness(mean(&gated)) as f32)
+    }
+}
+
+/// K-weighting filter from ITU-R BS.1770, a high shelf followed by a high pass, with coefficients
+/// calculated for the sample rate
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(rate: f64) -> Self {
+        let pi = std::f64::consts::PI;
+
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (pi * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1. + k / q + k * k;
+
+        let shelf = Biquad::new(
+            [(vh + vb * k / q + k * k) / a0, 2. * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (pi * f0 / rate).tan();
+        let a0 = 1. + k / q + k * k;
+
+        let high_pass = Biquad::new([1., -2., 1.], [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0]);
+
+        KWeighting { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Direct form II transposed biquad, with `a0` normalized to 1
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad { b, a, z: [0.; 2] }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+
+        output
+    }
+}
+
+/// Title and artist tags (Vorbis comments, ID3, etc.) of an audio file
+#[derive(Debug, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+impl Tags {
+    fn read(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string().trim().to_string();
+
+            if value.is_empty() {
+                continue;
+            }
+
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.title = Some(value),
+                Some(StandardTagKey::Artist) => self.artist = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    /// "Artist - Title", or just the title
+    pub fn display_name(&self) -> Option<String> {
+        match (&self.title, &self.artist) {
+            (Some(title), Some(artist)) => Some(format!("{} - {}", artist, title)),
+            (Some(title), None) => Some(title.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Read the title and artist of an audio file
+pub fn tags(path: &Path) -> Result<Tags> {
+    let mut probed = probe(path)?;
+    let mut tags = Tags::default();
+
+    // Tags found while probing (e.g. ID3), then the container's own (e.g. Vorbis comments)
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|metadata| metadata.current()) {
+        tags.read(revision);
+    }
+
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.read(revision);
+    }
+
+    Ok(tags)
+}
+
+fn probe(path: &Path) -> Result<ProbeResult> {
+    let file = std::fs::File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension() {
+        hint.with_extension(&extension.to_string_lossy());
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|why| format!("Unrecognized audio format: {}", why))?;
+
+    Ok(probed)
+}
+
+fn open(path: &Path) -> Result<Box<dyn FormatReader>> {
+    Ok(probe(path)?.format)
+}
+
+/// Length of an audio file in whole seconds, without decoding it
+pub fn duration(path: &Path) -> Result<usize> {
+    let format = open(path)?;
+    let track = format.default_track().ok_or("No audio track")?;
+
+    match (track.codec_params.n_frames, track.codec_params.sample_rate) {
+        (Some(frames), Some(sample_rate)) => Ok((frames / sample_rate as u64) as usize),
+        _ => Ok(decode(path)?.duration()),
+    }
+}
+
+/// Decode an audio file to samples
+#[instrument(err)]
+pub fn decode(path: &Path) -> Result<DecodedAudio> {
+    let mut format = open(path)?;
+    let track = format.default_track().ok_or("No audio track")?;
+    let track_id = track.id;
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|why| format!("Unsupported codec: {}", why))?;
+
+    let mut audio = DecodedAudio {
+        sample_rate: track.codec_params.sample_rate.unwrap_or_default(),
+        channels: Vec::new(),
+    };
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(why)) if why.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(why) => return Err(why.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Skip corrupt packets, like most players
+            Err(SymphoniaError::DecodeError(why)) => {
+                warn!("Skipping corrupt audio in {}: {}", path.display(), why);
+                continue;
+            }
+            Err(why) => return Err(why.into()),
+        };
+
+        let spec = *decoded.spec();
+        let frames = decoded.frames();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_planar_ref(decoded);
+
+        let channel_count = spec.channels.count();
+        audio.sample_rate = spec.rate;
+        audio.channels.resize_with(channel_count, Vec::new);
+
+        // Planar samples are stored channel after channel
+        for (channel, buffer) in audio.channels.iter_mut().enumerate() {
+            buffer.extend_from_slice(&samples.samples()[channel * frames..(channel + 1) * frames]);
+        }
+    }
+
+    if audio.channels.is_empty() || audio.sample_rate == 0 {
+        return Err("No audio decoded".into());
+    }
+
+    Ok(audio)
+}
+
+/// Whether LAAT was built with Ogg Vorbis encoding (the `transcode` feature)
+pub const CAN_ENCODE: bool = cfg!(feature = "transcode");
+
+/// Fail with an explanation if LAAT was built without Ogg Vorbis encoding
+pub fn check_encoder() -> Result<()> {
+    if CAN_ENCODE {
+        Ok(())
+    } else {
+        Err("Encoding Ogg Vorbis needs LAAT built with the `transcode` feature (`cargo install --path . --features transcode`)".into())
+    }
+}
+
+/// Encode audio as Ogg Vorbis, at an average bitrate in kbps
+#[cfg(feature = "transcode")]
+#[instrument(err, skip(audio))]
+pub fn encode_vorbis(audio: &DecodedAudio, bitrate: u32) -> Result<Vec<u8>> {
+    let sample_rate = NonZeroU32::new(audio.sample_rate).ok_or("Invalid sample rate")?;
+    let channels = u8::try_from(audio.channels.len())
+        .ok()
+        .and_then(NonZeroU8::new)
+        .ok_or("Invalid channel count")?;
+    let average_bitrate = NonZeroU32::new(bitrate * 1000).ok_or("Invalid bitrate")?;
+
+    let mut encoder = VorbisEncoderBuilder::new(sample_rate, channels, Vec::new())?
+        .bitrate_management_strategy(VorbisBitrateManagementStrategy::Abr { average_bitrate })
+        .build()?;
+
+    // Encode in blocks, to keep libvorbis' working buffers small
+    const BLOCK_SIZE: usize = 4096;
+    let frames = audio.channels[0].len();
+
+    for start in (0..frames).step_by(BLOCK_SIZE) {
+        let end = (start + BLOCK_SIZE).min(frames);
+        let block: Vec<&[f32]> = audio.channels.iter().map(|channel| &channel[start..end]).collect();
+
+        encoder.encode_audio_block(&block)?;
+    }
+
+    Ok(encoder.finish()?)
+}
+
+/// Encode audio as Ogg Vorbis, which needs the `transcode` feature
+#[cfg(not(feature = "transcode"))]
+pub fn encode_vorbis(_audio: &DecodedAudio, _bitrate: u32) -> Result<Vec<u8>> {
+    check_encoder().map(|_| Vec::new())
+}
+
+/// Decode an audio file and re-encode it as Ogg Vorbis
+pub fn transcode(path: &Path, bitrate: u32) -> Result<Vec<u8>> {
+    check_encoder()?;
+    let audio = decode(path)?;
+
+    encode_vorbis(&audio, bitrate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, channels: usize) -> DecodedAudio {
+        let sample_rate = 48000;
+        let samples: Vec<f32> = (0..sample_rate * 5)
+            .map(|index| amplitude * (2. * std::f32::consts::PI * frequency * index as f32 / sample_rate as f32).sin())
+            .collect();
+
+        DecodedAudio {
+            sample_rate: sample_rate as u32,
+            channels: vec![samples; channels],
+        }
+    }
+
+    #[test]
+    fn test_loudness() {
+        // A full scale 1kHz sine is -3.01 LUFS per channel
+        let mono = sine(1000., 1., 1).loudness().unwrap();
+        assert!((mono + 3.01).abs() < 0.1, "{}", mono);
+
+        let stereo = sine(1000., 1., 2).loudness().unwrap();
+        assert!(stereo.abs() < 0.1, "{}", stereo);
+
+        let mut quieter = sine(1000., 1., 1);
+        quieter.apply_gain(-6.);
+        assert!((quieter.loudness().unwrap() + 9.01).abs() < 0.1);
+        assert!((quieter.peak() - 0.501).abs() < 0.01);
+
+        assert_eq!(sine(1000., 0., 1).loudness(), None);
+    }
+
+    #[test]
+    fn test_encode_vorbis() {
+        let encoded = encode_vorbis(&sine(1000., 0.5, 2), 128);
+
+        if CAN_ENCODE {
+            assert!(encoded.unwrap().starts_with(b"OggS"));
+        } else {
+            assert!(encoded.unwrap_err().to_string().contains("`transcode` feature"));
+        }
+    }
+}
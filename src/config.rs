@@ -1,12 +1,14 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use crate::Result;
 use tokio::io::AsyncReadExt;
 
-pub async fn get_config_from_path(path: PathBuf) -> Result<LaatConfig> {
-    let mut file = tokio::fs::File::open(path).await?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).await?;
+const WORKSPACE_KEY: &str = "workspace";
+
+pub async fn get_config_from_path(path: impl AsRef<Path>) -> Result<LaatConfig> {
+    let contents = read_manifest(path.as_ref()).await?;
     let config: LaatConfig = toml::from_str(&contents)?;
 
     debug!("Extra: {:?}", config.extra);
@@ -14,22 +16,79 @@ pub async fn get_config_from_path(path: PathBuf) -> Result<LaatConfig> {
     Ok(config)
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// A LAAT.toml is either a single mod project, or a workspace of several member projects.
+pub enum Manifest {
+    Project(Box<LaatConfig>),
+    Workspace(WorkspaceConfig),
+}
+
+pub async fn get_manifest_from_path(path: impl AsRef<Path>) -> Result<Manifest> {
+    let contents = read_manifest(path.as_ref()).await?;
+    let value: toml::Value = toml::from_str(&contents)?;
+
+    if let Some(workspace) = value.get(WORKSPACE_KEY) {
+        let workspace: WorkspaceConfig = workspace.clone().try_into()?;
+
+        debug!("Workspace: {:?}", workspace);
+
+        Ok(Manifest::Workspace(workspace))
+    } else {
+        let config: LaatConfig = value.try_into()?;
+
+        debug!("Extra: {:?}", config.extra);
+
+        Ok(Manifest::Project(Box::new(config)))
+    }
+}
+
+async fn read_manifest(path: &Path) -> Result<String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|why| format!("Failed to open {}: {}", path.display(), why))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await?;
+
+    Ok(contents)
+}
+
+/// `[workspace]` section of a workspace LAAT.toml
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub struct WorkspaceConfig {
+    /// Folders of the member projects, each containing their own LAAT.toml
+    pub members: Vec<PathBuf>,
+
+    /// Asset folders shared by every member, searched after each member's own `assets_path`
+    #[serde(default)]
+    pub shared_assets: Vec<PathBuf>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
 pub struct LaatConfig {
+    /// Prefix for the mod, used for addon and class names
     pub prefix: String,
+    /// Display name of the mod
     pub name: String,
 
+    /// Folder generated addons are written to
     #[serde(default = "default_build_path")]
     pub build_path: String,
+    /// Folder containing assets for plugins (music, compositions, etc.)
     #[serde(default = "default_assets_path")]
     pub assets_path: String,
+    /// Extra asset folders, searched after `assets_path`
+    #[serde(default)]
+    pub shared_assets: Vec<String>,
+    /// Folder containing hand-written addons
     #[serde(default = "default_addons_path")]
     pub addons_path: String,
+    /// Folder packed PBOs are written to
     #[serde(default = "default_release_path")]
     pub release_path: String,
+    /// Folder containing signing keys
     #[serde(default = "default_keys_path")]
     pub keys_path: String,
 
+    /// Compiler plugins to run during `laat build`
     #[serde(default)]
     pub plugins: Vec<String>,
 
@@ -39,11 +98,112 @@ pub struct LaatConfig {
     #[serde(default)]
     pub pack: PackConfig,
 
+    /// Steam Workshop mods this mod depends on, keyed by workshop ID
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, DependencyConfig>,
+
+    /// Generate a server bundle when packing
+    #[serde(default)]
+    pub server: Option<ServerConfig>,
+
+    /// Build profiles, selected with `--profile`
+    #[serde(default)]
+    pub profile: HashMap<String, ProfileConfig>,
+
     #[serde(flatten)]
+    #[schemars(skip)]
     pub extra: toml::Value
 }
 
 impl LaatConfig {
+    /// All asset folders for this project, starting with `assets_path`
+    pub fn asset_paths(&self) -> Vec<String> {
+        let mut paths = vec![self.assets_path.clone()];
+        paths.extend(self.shared_assets.iter().cloned());
+
+        paths
+    }
+
+    /// Apply the overrides from `[profile.<name>]` to this config
+    pub fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self
+            .profile
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown profile: {}", name))?;
+
+        debug!("Applying profile {}: {:?}", name, profile);
+
+        if let Some(suffix) = profile.prefix_suffix {
+            if !suffix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!(
+                    "Invalid prefix_suffix in [profile.{}]: {:?}. Only letters, numbers and '_' are allowed.",
+                    name, suffix
+                )
+                .into());
+            }
+
+            self.prefix.push_str(&suffix);
+        }
+
+        if let Some(mod_name) = profile.name {
+            self.name = mod_name;
+        }
+
+        if let Some(plugins) = profile.plugins {
+            self.plugins = plugins;
+        }
+
+        if let Some(pack) = profile.pack {
+            self.pack.apply(pack);
+        }
+
+        if let Some(build_path) = profile.build_path {
+            self.build_path = build_path;
+        }
+
+        if let Some(release_path) = profile.release_path {
+            self.release_path = release_path;
+        }
+
+        if let Some(keys_path) = profile.keys_path {
+            self.keys_path = keys_path;
+        }
+
+        if let Some(workshop_id) = profile.workshop_id {
+            self.release.workshop_id = workshop_id;
+        }
+
+        Ok(())
+    }
+
+    /// CfgPatches classes of all dependencies, for `requiredAddons[]`
+    pub fn required_addons(&self) -> Vec<String> {
+        self.dependencies
+            .values()
+            .flat_map(|dependency| dependency.patches().iter().cloned())
+            .collect()
+    }
+
+    /// Mod folders of all dependencies, for the `-mod=` startup parameter
+    pub fn dependency_mods(&self) -> Vec<String> {
+        self.dependencies
+            .iter()
+            .map(|(id, dependency)| format!("@{}", dependency.name().unwrap_or(id)))
+            .collect()
+    }
+
+    /// Workshop IDs of all dependencies
+    pub fn dependency_ids(&self) -> Result<Vec<usize>> {
+        self.dependencies
+            .keys()
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| format!("Invalid workshop ID in [dependencies]: {}", id).into())
+            })
+            .collect()
+    }
+
     pub fn released_addon_path(&self) -> String {
         let release_path = format!(
             "{}/@{}",
@@ -55,17 +215,122 @@ impl LaatConfig {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct PackConfig {
     #[serde(default)]
     pub include_folders: Vec<PathBuf>,
     #[serde(default)]
     pub excludes: Vec<String>,
     #[serde(default)]
-    pub header_extensions: Vec<String>
+    pub header_extensions: Vec<String>,
+    /// Sign PBOs when packing. `laat pack` signs only when this is true, `laat ship` unless it
+    /// is false.
+    #[serde(default)]
+    pub sign: Option<bool>,
+    /// Always pack with windows filenames
+    #[serde(default)]
+    pub windows: bool,
+}
+
+impl PackConfig {
+    /// Override the fields set in a profile's `pack` table
+    pub fn apply(&mut self, overrides: ProfilePackConfig) {
+        if let Some(include_folders) = overrides.include_folders {
+            self.include_folders = include_folders;
+        }
+
+        if let Some(excludes) = overrides.excludes {
+            self.excludes = excludes;
+        }
+
+        if let Some(header_extensions) = overrides.header_extensions {
+            self.header_extensions = header_extensions;
+        }
+
+        if let Some(sign) = overrides.sign {
+            self.sign = Some(sign);
+        }
+
+        if let Some(windows) = overrides.windows {
+            self.windows = windows;
+        }
+    }
+}
+
+/// `pack` table of a profile. Unset fields are inherited from the top-level `[pack]`.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct ProfilePackConfig {
+    pub include_folders: Option<Vec<PathBuf>>,
+    pub excludes: Option<Vec<String>>,
+    pub header_extensions: Option<Vec<String>>,
+    pub sign: Option<bool>,
+    pub windows: Option<bool>,
+}
+
+/// Entry in the `[dependencies]` section of LAAT.toml
+///
+/// ```toml
+/// [dependencies]
+/// 450814997 = ["cba_main"]
+/// 463939057 = { name = "ace", patches = ["ace_main"] }
+/// ```
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+#[serde(untagged)]
+pub enum DependencyConfig {
+    /// CfgPatches classes of the dependency
+    Patches(Vec<String>),
+    Detailed {
+        /// Mod folder name, without the `@`. Defaults to the workshop ID.
+        name: Option<String>,
+        /// CfgPatches classes of the dependency
+        patches: Vec<String>,
+    },
+}
+
+impl DependencyConfig {
+    pub fn name(&self) -> Option<&String> {
+        match self {
+            DependencyConfig::Patches(_) => None,
+            DependencyConfig::Detailed { name, .. } => name.as_ref(),
+        }
+    }
+
+    pub fn patches(&self) -> &[String] {
+        match self {
+            DependencyConfig::Patches(patches) => patches,
+            DependencyConfig::Detailed { patches, .. } => patches,
+        }
+    }
+}
+
+/// `[server]` section of LAAT.toml
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub struct ServerConfig {
+    /// Folder to write the server bundle to, defaults to `{release_path}/server`
+    pub path: Option<String>,
+    /// Server name, defaults to the mod name
+    pub hostname: Option<String>,
+    /// Difficulty for the mission rotation
+    #[serde(default = "default_difficulty")]
+    pub difficulty: String,
+}
+
+/// `[profile.<name>]` section of LAAT.toml. Every field overrides the matching top-level setting.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, Default)]
+pub struct ProfileConfig {
+    /// Appended to `prefix`, so classnames don't clash with other profiles (e.g. "_dev")
+    pub prefix_suffix: Option<String>,
+    pub name: Option<String>,
+    pub plugins: Option<Vec<String>>,
+    pub pack: Option<ProfilePackConfig>,
+    pub build_path: Option<String>,
+    pub release_path: Option<String>,
+    pub keys_path: Option<String>,
+    /// Steam Workshop item to release this profile to
+    pub workshop_id: Option<usize>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, Default)]
 pub struct ReleaseConfig {
     #[serde(default = "arma_app_id")]
     pub app_id: usize,
@@ -77,6 +342,10 @@ fn arma_app_id() -> usize {
     107410
 }
 
+fn default_difficulty() -> String {
+    "Regular".to_string()
+}
+
 fn default_build_path() -> String {
     "build".to_string()
 }
@@ -96,3 +365,65 @@ fn default_release_path() -> String {
 fn default_keys_path() -> String {
     "keys".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+prefix = "LAAT"
+name = "LAAT Test Mod"
+plugins = ["music", "missions"]
+
+[pack]
+sign = true
+excludes = ["*.psd"]
+
+[profile.dev]
+prefix_suffix = "_dev"
+plugins = ["music"]
+pack = { windows = true }
+workshop_id = 1234
+
+[profile.unsigned]
+pack = { sign = false }
+"#;
+
+    #[test]
+    fn test_apply_profile() -> Result<()> {
+        let mut config: LaatConfig = toml::from_str(CONFIG)?;
+        config.apply_profile("dev")?;
+
+        assert_eq!(config.prefix, "LAAT_dev");
+        assert_eq!(config.name, "LAAT Test Mod");
+        assert_eq!(config.plugins, vec!["music".to_string()]);
+        assert!(config.pack.windows);
+        assert_eq!(config.pack.sign, Some(true));
+        assert_eq!(config.pack.excludes, vec!["*.psd".to_string()]);
+        assert_eq!(config.release.workshop_id, 1234);
+        assert_eq!(config.released_addon_path(), "release/@LAAT_dev");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_disables_signing() -> Result<()> {
+        let mut config: LaatConfig = toml::from_str(CONFIG)?;
+        config.apply_profile("unsigned")?;
+
+        assert_eq!(config.pack.sign, Some(false));
+        assert!(!config.pack.windows);
+        assert_eq!(config.pack.excludes, vec!["*.psd".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_profile() -> Result<()> {
+        let mut config: LaatConfig = toml::from_str(CONFIG)?;
+
+        assert!(config.apply_profile("release").is_err());
+
+        Ok(())
+    }
+}
@@ -1,10 +1,29 @@
 use crate::Result;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use tokio::io::AsyncWriteExt;
 
 pub type BuildContext = super::config::LaatConfig;
 
+impl BuildContext {
+    /// Deserialize a plugin's `[section]` table from LAAT.toml into a typed config.
+    ///
+    /// A missing section is treated as an empty table, so `#[serde(default)]` fields still
+    /// apply. Errors name the offending section.
+    pub fn plugin_config<T: DeserializeOwned>(&self, section: &str) -> Result<T> {
+        let value = self
+            .extra
+            .get(section)
+            .cloned()
+            .unwrap_or_else(|| toml::Value::Table(toml::value::Table::new()));
+
+        value
+            .try_into()
+            .map_err(|why| format!("Invalid [{}] section in LAAT.toml: {}", section, why).into())
+    }
+}
+
 /// An Addon manager that takes the BuildContext and Addon name. Then prepares prefixed paths
 /// for asset paths that are passed to it, then copies all the assets over into the build
 /// folder. It also manages the config.cpp creation.
@@ -15,10 +34,12 @@ pub struct AddonManager {
     /// Build context from LAAT
     build_context: BuildContext,
 
-    /// Map containing ./assets paths and maps them to Addon prefixed paths.
+    /// Map containing Addon prefixed paths and the ./assets paths copied to them. Keyed by
+    /// destination, so one asset can be copied to several places in the addon.
     asset_map: HashMap<PathBuf, PathBuf>,
 
-    file_map: HashMap<PathBuf, String>,
+    /// Map containing Addon prefixed paths and the generated contents written to them
+    file_map: HashMap<PathBuf, Vec<u8>>,
 }
 
 impl AddonManager {
@@ -42,6 +63,11 @@ impl AddonManager {
         format!(r"{}/{}", self.build_context.prefix, self.addon).into()
     }
 
+    /// Returns the PBO prefix the addon is packed with, used for paths inside configs
+    pub fn pbo_prefix(&self) -> String {
+        format!(r"{}\{}", self.build_context.prefix, self.addon)
+    }
+
     pub fn build_path(&self) -> PathBuf {
         self.build_context.build_path.clone().into()
     }
@@ -67,7 +93,7 @@ impl AddonManager {
             return Err(format!("Failed to get file name for: {:?}", asset_path).into());
         }
 
-        self.asset_map.insert(asset_path, addon_path.clone());
+        self.asset_map.insert(addon_path.clone(), asset_path);
 
         Ok(addon_path)
     }
@@ -78,7 +104,13 @@ impl AddonManager {
     async fn copy_assets(&self) -> Result<()> {
         let mut futs = Vec::new();
 
-        for (asset, addon_path) in self.asset_map.clone().into_iter() {
+        for (addon_path, asset) in self.asset_map.clone().into_iter() {
+            // Generated files take precedence over copied assets
+            if self.file_map.contains_key(&addon_path) {
+                debug!("Skipping {}, replaced by a generated file", asset.display());
+                continue;
+            }
+
             debug!("Copying {} > {}", asset.display(), addon_path.display());
             let mut dest = self.build_path();
             dest.push(addon_path);
@@ -105,6 +137,12 @@ impl AddonManager {
 
     /// Set the value to write to target file
     pub fn add_file(&mut self, buffer: String, path: PathBuf) {
+        self.add_binary_file(buffer.into_bytes(), path);
+    }
+
+    /// Set the bytes to write to target file, for generated files that aren't text (e.g.
+    /// transcoded audio)
+    pub fn add_binary_file(&mut self, buffer: Vec<u8>, path: PathBuf) {
         let mut file_path = PathBuf::new();
         file_path.push(self.addon_path());
         file_path.push(path);
@@ -114,7 +152,7 @@ impl AddonManager {
 
     #[instrument(err, skip(self))]
     async fn write_files(&self) -> Result<()> {
-        for (path, string) in self.file_map.clone().into_iter() {
+        for (path, buffer) in self.file_map.clone().into_iter() {
             debug!("Writing file: {}", path.display());
             let mut file_path = self.build_path();
             file_path.push(path);
@@ -124,7 +162,7 @@ impl AddonManager {
             }
 
             let mut file = tokio::fs::File::create(file_path).await?;
-            file.write_all(string.as_bytes()).await?;
+            file.write_all(&buffer).await?;
         }
 
         Ok(())
@@ -162,6 +200,7 @@ mod tests {
             name: "LAAT Test Mod".to_string(),
             build_path: "build".to_string(),
             assets_path: "assets".to_string(),
+            shared_assets: vec![],
             addons_path: "addons".to_string(),
             release_path: "release".to_string(),
             plugins: vec![],
@@ -169,7 +208,12 @@ mod tests {
                 include_folders: vec![],
                 excludes: vec![],
                 header_extensions: vec![],
+                sign: None,
+                windows: false,
             },
+            dependencies: Default::default(),
+            server: None,
+            profile: HashMap::new(),
             extra: Value::Float(0.0),
             keys_path: "keys".to_string(),
             release: ReleaseConfig {
@@ -179,6 +223,34 @@ mod tests {
         }
     }
 
+    #[derive(Debug, serde::Deserialize)]
+    struct TestPluginConfig {
+        file: String,
+        #[serde(default)]
+        count: usize,
+    }
+
+    #[test]
+    fn test_plugin_config() -> Result<()> {
+        let mut context = build_context();
+        context.extra = toml::from_str("[test]\nfile = \"./test.toml\"")?;
+
+        let config: TestPluginConfig = context.plugin_config("test")?;
+
+        assert_eq!(config.file, "./test.toml");
+        assert_eq!(config.count, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_config_missing_field() {
+        let result: Result<TestPluginConfig> = build_context().plugin_config("test");
+
+        let why = result.unwrap_err().to_string();
+        assert!(why.contains("[test]"));
+    }
+
     #[test]
     fn test_asset_pathing() -> Result<()> {
         let mut manager = AddonManager::from_context("Test".to_string(), build_context());
@@ -0,0 +1,101 @@
+//! Config class names generated from file, folder and map names.
+//!
+//! Class names in Arma configs may only contain ASCII letters, digits and underscores, and can't
+//! start with a digit.
+
+/// Turn a name into a valid class name. Accented letters are replaced with their ASCII letter,
+/// apostrophes are dropped, and any other run of characters becomes a single underscore.
+///
+/// `"Don't Stop Me Now (Live)"` becomes `Dont_Stop_Me_Now_Live`, and `"99 Luftballons"` becomes
+/// `_99_Luftballons`.
+pub fn sanitize(name: &str) -> String {
+    let mut identifier = String::with_capacity(name.len());
+
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            identifier.push(c);
+        } else if c == '\'' || c == '\u{2019}' {
+            continue;
+        } else if let Some(ascii) = transliterate(c) {
+            identifier.push_str(ascii);
+        } else if !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+    }
+
+    let identifier = identifier.trim_matches('_');
+
+    match identifier.chars().next() {
+        None => "_".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("_{}", identifier),
+        Some(_) => identifier.to_string(),
+    }
+}
+
+/// Whether a name can be used as a class name as is
+pub fn is_valid(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// ASCII replacement for common accented Latin letters
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' => "c",
+        'Ç' => "C",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' => "E",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ñ' => "n",
+        'Ñ' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ß' => "ss",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' => "Y",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("Danger_Zone"), "Danger_Zone");
+        assert_eq!(sanitize("Don't Stop Me Now (Live)"), "Dont_Stop_Me_Now_Live");
+        assert_eq!(sanitize("99 Luftballons"), "_99_Luftballons");
+        assert_eq!(sanitize("Mötley Crüe - Kickstart"), "Motley_Crue_Kickstart");
+        assert_eq!(sanitize("夜に駆ける"), "_");
+        assert_eq!(sanitize("a--b  c"), "a_b_c");
+
+        for name in ["Danger_Zone", "Don't Stop Me Now (Live)", "99 Luftballons", "夜に駆ける"].iter() {
+            assert!(is_valid(&sanitize(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("LAAT_Music"));
+        assert!(is_valid("_99"));
+        assert!(!is_valid("99"));
+        assert!(!is_valid("Take On Me"));
+        assert!(!is_valid(""));
+    }
+}
@@ -24,10 +24,13 @@ extern crate async_trait;
 extern crate tracing;
 
 use crate::config::LaatConfig;
+use crate::config::ServerConfig;
 use crate::context::BuildContext;
+use crate::plugins::util::config_string;
 use armake2::pbo::cmd_build;
 use futures_util::future::join_all;
 use handlebars::Handlebars;
+use schemars::gen::SchemaGenerator;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::io::Read;
@@ -43,6 +46,11 @@ pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 const PBOPREFIX: &str = "$PBOPREFIX$";
+const SCHEMA_NAME: &str = "laat";
+const WORKSHOP_URL: &str = "https://steamcommunity.com/sharedfiles/filedetails/?id=";
+const STARTUP_PARAMETERS: &str = "startup_parameters.txt";
+/// The Unix epoch as a Windows FILETIME (100ns intervals since 1601-01-01)
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
 const PROJECT_FOLDERS: &[&str] = &["addons", "assets", "build", "release"];
 const GITIGNORE: &str = r"
 build
@@ -142,13 +150,27 @@ impl LaatCompiler {
         Ok(())
     }
 
+    /// Build and pack the mod, signing the PBOs unless `pack.sign` is false
+    #[instrument(skip(self))]
+    pub async fn ship(&self, windows: bool) -> Result<()> {
+        self.build(None).await?;
+
+        let sign = self.get_context().pack.sign.unwrap_or(true);
+        self.pack(sign, windows).await
+    }
+
     #[instrument(skip(self))]
     pub async fn pack(&self, sign: bool, windows: bool) -> Result<()> {
         info!("Packaging project...");
+        let LaatConfig { pack, .. } = self.get_context();
+        let sign = sign || pack.sign == Some(true);
+        let windows = windows || pack.windows;
+
         let release_path = self.get_context().released_addon_path();
 
         self.setup_release_folder(&release_path, windows).await?;
         self.create_mod_cpp(&release_path).await?;
+        self.create_meta_cpp(&release_path).await?;
 
         self.create_pbos(&release_path, windows).await?;
 
@@ -156,6 +178,10 @@ impl LaatCompiler {
             self.sign_pbos(&release_path, windows).await?;
         }
 
+        if let Some(server) = self.get_context().server {
+            self.create_server_bundle(&server).await?;
+        }
+
         Ok(())
     }
 
@@ -218,7 +244,7 @@ impl LaatCompiler {
 
         info!("Signing PBOs...");
 
-        self.sign_pbos(&release_path, false).await?;
+        self.sign_pbos(&release_path, self.get_context().pack.windows).await?;
 
         Ok(())
     }
@@ -399,9 +425,132 @@ impl LaatCompiler {
         Ok(())
     }
 
+    /// Create the meta.cpp read by the Arma 3 launcher and servers
+    pub async fn create_meta_cpp(&self, release_path: &str) -> Result<()> {
+        let context = self.get_context();
+
+        // Windows FILETIME, in 100ns intervals since 1601-01-01
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs()
+            * 10_000_000
+            + FILETIME_UNIX_EPOCH;
+
+        let meta = ModMeta {
+            publishedid: context.release.workshop_id,
+            name: config_string(&context.name),
+            timestamp,
+        };
+
+        let handlebars = create_handlebars()?;
+        let rendered = handlebars.render("meta.cpp", &meta)?;
+        create_and_write_file(format!("{}/meta.cpp", release_path), rendered).await?;
+
+        Ok(())
+    }
+
+    /// Create a server bundle with the server key, `-mod=` startup parameter, and a server.cfg
+    /// with a mission rotation of the generated missions.
+    #[instrument(skip(self), err)]
+    pub async fn create_server_bundle(&self, server: &ServerConfig) -> Result<()> {
+        let context = self.get_context();
+        info!("Creating server bundle...");
+
+        let server_path: PathBuf = server
+            .path
+            .clone()
+            .unwrap_or_else(|| format!("{}/server", context.release_path))
+            .into();
+
+        let keys_path = server_path.join("keys");
+        tokio::fs::create_dir_all(&keys_path).await?;
+
+        match self.get_keys().await {
+            Ok((_, pubkey_path)) => {
+                if let Some(file_name) = pubkey_path.file_name() {
+                    tokio::fs::copy(&pubkey_path, keys_path.join(file_name)).await?;
+                }
+            }
+            Err(why) => warn!("Server bundle will not contain a key: {}", why),
+        }
+
+        let missions = if context.plugins.iter().any(|p| p == "missions") {
+            plugins::mission_class_names(&context)?
+        } else {
+            Vec::new()
+        };
+
+        let mut mods = context.dependency_mods();
+        mods.push(format!("@{}", context.prefix));
+
+        let bundle = ServerBundle {
+            hostname: server.hostname.clone().unwrap_or_else(|| context.name.clone()),
+            difficulty: server.difficulty.clone(),
+            missions,
+        };
+
+        let handlebars = create_handlebars()?;
+        let rendered = handlebars.render("server.cfg", &bundle)?;
+        create_and_write_file(server_path.join("server.cfg"), rendered).await?;
+
+        create_and_write_file(
+            server_path.join(STARTUP_PARAMETERS),
+            format!("-mod={}\n", mods.join(";")),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Import an Eden mission folder or mission.sqm into the missions plugin's `missions_folder`.
+    #[instrument(skip(self), err)]
+    pub async fn import_mission(&self, path: PathBuf, map: Option<String>) -> Result<()> {
+        plugins::import_mission(&self.get_context(), &path, map)?;
+
+        Ok(())
+    }
+
+    /// Write JSON Schemas for LAAT.toml and the enabled plugins' config files to `output`.
+    #[instrument(skip(self), err)]
+    pub async fn schema(&self, output: PathBuf) -> Result<()> {
+        let mut generator = SchemaGenerator::default();
+
+        let mut sections = Vec::new();
+        let mut files = Vec::new();
+
+        for (name, plugin) in self.plugins.iter() {
+            if let Some(schema) = plugin.config_schema(&mut generator) {
+                sections.push((name.clone(), schema));
+            }
+
+            files.extend(plugin.file_schemas());
+        }
+
+        let mut laat_schema = generator.root_schema_for::<LaatConfig>();
+        laat_schema.schema.object().properties.extend(sections);
+
+        files.push((SCHEMA_NAME.to_string(), laat_schema));
+
+        tokio::fs::create_dir_all(&output).await?;
+
+        for (name, schema) in files {
+            let mut path = output.clone();
+            path.push(format!("{}.schema.json", name));
+
+            info!("Writing schema: {}", path.display());
+            create_and_write_file(&path, serde_json::to_string_pretty(&schema)?).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn from_path(path: PathBuf) -> Result<Self> {
         let config = config::get_config_from_path(path).await?;
 
+        Self::from_config(config)
+    }
+
+    pub fn from_config(config: LaatConfig) -> Result<Self> {
         let mut plugins = HashMap::new();
 
         for plugin in config.plugins.iter() {
@@ -480,10 +629,12 @@ impl LaatCompiler {
             release.change_notes.join(" ")
         };
 
+        let dependency_ids = context.dependency_ids()?;
+
         // 2. Strip " from changelog
         let changenotes = change_log.replace("\"", "");
 
-        let mut content_folder: PathBuf = std::env::var("PWD")?.into();
+        let mut content_folder: PathBuf = std::env::current_dir()?;
         content_folder.push(context.released_addon_path());
 
         // 3. render workshop_upload.vdf
@@ -492,6 +643,7 @@ impl LaatCompiler {
             file_id: context.release.workshop_id,
             content_folder,
             changenotes,
+            dependencies: dependency_ids,
         };
 
         debug!(?workshop_item, "Rendering SteamCMD VDF");
@@ -522,16 +674,36 @@ impl LaatCompiler {
 
         steamcmd.spawn()?.wait().await?;
 
+        for id in workshop_item.dependencies.iter() {
+            info!("Required Item: {}{}", WORKSHOP_URL, id);
+        }
+
         Ok(())
     }
 }
 
+#[derive(Debug, Serialize)]
+struct ModMeta {
+    publishedid: usize,
+    name: String,
+    timestamp: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ServerBundle {
+    hostname: String,
+    difficulty: String,
+    missions: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 struct WorkshopItem {
     app_id: usize,
     file_id: usize,
     content_folder: PathBuf,
     changenotes: String,
+    /// Workshop IDs of the items in `[dependencies]`
+    dependencies: Vec<usize>,
 }
 
 pub fn create_handlebars<'a>() -> Result<Handlebars<'a>> {
@@ -542,11 +714,26 @@ pub fn create_handlebars<'a>() -> Result<Handlebars<'a>> {
         include_str!("../templates/music/cfg_music.ht"),
     )?;
 
+    handlebars.register_template_string(
+        "sounds_addon",
+        include_str!("../templates/sounds/cfg_sounds.ht"),
+    )?;
+
     handlebars.register_template_string(
         "missions_addon",
         include_str!("../templates/missions/cfg_missions.ht"),
     )?;
 
+    handlebars.register_template_string(
+        "missions_description",
+        include_str!("../templates/missions/description.ext.ht"),
+    )?;
+
+    handlebars.register_template_string(
+        "missions_slots",
+        include_str!("../templates/missions/slots.sqe.ht"),
+    )?;
+
     handlebars.register_template_string("laat.toml", include_str!("../templates/laat.toml.ht"))?;
 
     handlebars
@@ -559,6 +746,13 @@ pub fn create_handlebars<'a>() -> Result<Handlebars<'a>> {
 
     handlebars.register_template_string("mod.cpp", include_str!("../templates/mod.cpp.ht"))?;
 
+    handlebars.register_template_string("meta.cpp", include_str!("../templates/meta.cpp.ht"))?;
+
+    handlebars.register_template_string(
+        "server.cfg",
+        include_str!("../templates/server/server.cfg.ht"),
+    )?;
+
     Ok(handlebars)
 }
 
@@ -576,11 +770,23 @@ use plugins::Plugin;
 pub mod plugins {
     use super::context::BuildContext;
     use crate::Result;
+    use schemars::gen::SchemaGenerator;
+    use schemars::schema::{RootSchema, Schema};
 
     #[async_trait]
     pub trait Plugin {
         async fn build(&self, _: BuildContext) -> Result<()>;
         fn name(&self) -> String;
+
+        /// JSON Schema for this plugin's section in LAAT.toml, if it has one.
+        fn config_schema(&self, _: &mut SchemaGenerator) -> Option<Schema> {
+            None
+        }
+
+        /// JSON Schemas for any other config files this plugin reads, keyed by name.
+        fn file_schemas(&self) -> Vec<(String, RootSchema)> {
+            Vec::new()
+        }
     }
 
     pub fn get_plugin(name: &str) -> Result<Box<dyn Plugin>> {
@@ -594,6 +800,7 @@ pub mod plugins {
     pub fn plugins() -> Vec<Box<dyn Plugin>> {
         vec![
             Box::new(MusicPlugin),
+            Box::new(SoundsPlugin),
             Box::new(AddonPlugin),
             Box::new(CustomsPlugin),
             Box::new(KitPlugin),
@@ -604,6 +811,9 @@ pub mod plugins {
     mod music;
     pub use music::MusicPlugin;
 
+    mod sounds;
+    pub use sounds::SoundsPlugin;
+
     mod addons;
     pub use addons::AddonPlugin;
 
@@ -614,9 +824,65 @@ pub mod plugins {
     pub use kits::KitPlugin;
 
     mod missions;
-    pub use missions::MissionPlugin;
+    pub use missions::{import_mission, mission_class_names, MissionPlugin};
+
+    pub(crate) mod util;
 }
 
 pub mod context;
 
+pub mod workspace;
+
+mod audio;
+
 mod config;
+
+mod identifier;
+
+mod terrain;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workshop_vdf() -> Result<()> {
+        let mut workshop_item = WorkshopItem {
+            app_id: 107410,
+            file_id: 1234,
+            content_folder: PathBuf::from("/mod/release/@LAAT"),
+            changenotes: "Fixes".to_string(),
+            dependencies: vec![450814997, 463939057],
+        };
+
+        let vdf = create_handlebars()?.render("workshop_upload.vdf", &workshop_item)?;
+        assert!(vdf.contains("\"dependencies\""));
+        assert!(vdf.contains("\"0\" \"450814997\""));
+        assert!(vdf.contains("\"1\" \"463939057\""));
+
+        workshop_item.dependencies.clear();
+        let vdf = create_handlebars()?.render("workshop_upload.vdf", &workshop_item)?;
+        assert!(!vdf.contains("dependencies"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_meta_cpp() -> Result<()> {
+        let meta = ModMeta {
+            publishedid: 1234,
+            name: config_string(r#"17th "Legion" Mod"#),
+            timestamp: FILETIME_UNIX_EPOCH,
+        };
+
+        let rendered = create_handlebars()?.render("meta.cpp", &meta)?;
+        let config = armake2::config::Config::from_string(rendered, None, &Vec::new())?;
+
+        match config.get("name") {
+            Some(armake2::config::ConfigEntry::StringEntry(name)) => assert_eq!(name, r#"17th "Legion" Mod"#),
+            _ => panic!("Missing name"),
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use laat::InitSettings;
 use laat::LaatCompiler;
 use laat::ReleaseSettings;
+use laat::workspace::Workspace;
 use std::path::PathBuf;
 use structopt::StructOpt;
 use tracing::error;
@@ -15,6 +16,14 @@ struct Opts {
     /// Point to your LAAT.toml file
     config_file: PathBuf,
 
+    #[structopt(short, long)]
+    /// Only run on this workspace member (folder name or prefix)
+    member: Option<String>,
+
+    #[structopt(long)]
+    /// Build profile from LAAT.toml to apply (e.g. `dev` for `[profile.dev]`)
+    profile: Option<String>,
+
     #[structopt(long)]
     debug: bool,
 }
@@ -48,6 +57,14 @@ enum Command {
     Sign {},
     /// Publish your mod to the Steam Workshop
     Release(ReleaseSettings),
+    /// Manage missions for the missions plugin
+    Mission(MissionCommand),
+    /// Write JSON Schemas for LAAT.toml and plugin configs
+    Schema {
+        #[structopt(short, long, parse(from_os_str), default_value = "schemas")]
+        /// Folder to write the schemas to
+        output: PathBuf,
+    },
     /// Runs clean, build, pack, sign, and optionally release
     Ship {
         #[structopt(long)]
@@ -56,6 +73,18 @@ enum Command {
     },
 }
 
+#[derive(Debug, StructOpt)]
+enum MissionCommand {
+    /// Copy an Eden mission folder or mission.sqm into missions_folder, derapifying mission.sqm
+    Import {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(long)]
+        /// Map to import the mission for, defaults to the extension of the mission folder
+        map: Option<String>,
+    },
+}
+
 #[tokio::main]
 async fn main() {
     if let Err(why) = run().await {
@@ -99,37 +128,66 @@ fn init_logging(opts: &Opts) -> laat::Result<()> {
 }
 
 async fn run_command(opts: Opts) -> laat::Result<()> {
-    let laat = if let Command::Init(init) = &opts.command {
-        LaatCompiler::init(init.clone()).await
-    } else {
-        LaatCompiler::from_path(opts.config_file).await
-    }?;
+    if let Command::Init(init) = opts.command {
+        LaatCompiler::init(init).await?;
+        return Ok(());
+    }
+
+    let mut command = opts.command;
 
-    match opts.command {
+    // Members run in their own folder, so resolve the path before entering them
+    if let Command::Mission(MissionCommand::Import { path, .. }) = &mut command {
+        *path = std::fs::canonicalize(path.as_path())
+            .map_err(|why| format!("Failed to find {}: {}", path.display(), why))?;
+    }
+
+    let workspace = Workspace::from_path(opts.config_file, opts.member, opts.profile).await?;
+
+    if let Command::Mission(_) = command {
+        if workspace.members().len() > 1 {
+            return Err("Choose the workspace member to import the mission into with --member".into());
+        }
+    }
+
+    for member in workspace.members() {
+        member.enter()?;
+        run_member_command(member.compiler(), &command).await?;
+    }
+
+    Ok(())
+}
+
+async fn run_member_command(laat: &LaatCompiler, command: &Command) -> laat::Result<()> {
+    match command {
         Command::Build { plugin } => {
-            laat.build(plugin).await?;
+            laat.build(plugin.clone()).await?;
         }
         Command::Clean {} => {
             laat.clean_build().await?;
         }
         Command::Pack { sign, windows } => {
-            laat.pack(sign, windows).await?;
+            laat.pack(*sign, *windows).await?;
         }
         Command::Keygen { name } => {
-            laat.create_keys(name).await?;
+            laat.create_keys(name.clone()).await?;
         }
         Command::Sign {} => {
             laat.sign().await?;
         }
         Command::Release(release) => {
-            laat.release(release).await?;
+            laat.release(release.clone()).await?;
+        }
+        Command::Mission(MissionCommand::Import { path, map }) => {
+            laat.import_mission(path.clone(), map.clone()).await?;
+        }
+        Command::Schema { output } => {
+            laat.schema(output.clone()).await?;
         }
         Command::Ship { windows } => {
-            laat.build(None).await?;
-            laat.pack(true, windows).await?;
+            laat.ship(*windows).await?;
         }
         _ => {}
     }
-    
+
     Ok(())
 }
@@ -2,9 +2,13 @@
 //!
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use crate::context::AddonManager;
 use super::{Plugin, BuildContext};
 use crate::Result;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{RootSchema, Schema};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 use tokio::io::AsyncReadExt;
 
@@ -18,14 +22,14 @@ type MultiItemEntry = (usize, String, Location);
 
 type ItemEntry = (String, Location);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 enum Item {
     Multi(MultiItemEntry),
     Single(ItemEntry)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 enum Location {
     Vest,
@@ -35,7 +39,7 @@ enum Location {
     AddAssign
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 struct Kit {
     weapons: Vec<String>,
     vest: String,
@@ -50,10 +54,10 @@ struct Kit {
     components: Vec<String>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 struct Component(Vec<Item>);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 struct KitFile {
     kits: HashMap<String, Kit>,
     components: HashMap<String, Component>
@@ -85,15 +89,34 @@ impl Plugin for KitPlugin {
     fn name(&self) -> String {
         "kits".to_string()
     }
+
+    fn config_schema(&self, generator: &mut SchemaGenerator) -> Option<Schema> {
+        Some(generator.subschema_for::<KitSettings>())
+    }
+
+    fn file_schemas(&self) -> Vec<(String, RootSchema)> {
+        vec![(KIT_SETTINGS_KEY.to_string(), SchemaGenerator::default().into_root_schema_for::<KitFile>())]
+    }
 }
 
-const FILE_FIELD: &str = "kits.file";
-const DEFAULT_FILE: &str = "kits.toml";
+const KIT_SETTINGS_KEY: &str = "kits";
+
+/// `[kits]` section of LAAT.toml
+#[derive(Debug, Deserialize, JsonSchema)]
+struct KitSettings {
+    /// Path to the kits file
+    #[serde(default = "default_kits_file")]
+    file: PathBuf,
+}
+
+fn default_kits_file() -> PathBuf {
+    "kits.toml".into()
+}
 
 async fn load_kit_config(build_config: &BuildContext) -> Result<KitFile> {
-    let file_path = build_config.extra.get(FILE_FIELD).map(|v| v.as_str()).flatten().unwrap_or(DEFAULT_FILE);
+    let settings: KitSettings = build_config.plugin_config(KIT_SETTINGS_KEY)?;
 
-    let mut kit_file = tokio::fs::File::open(file_path).await?;
+    let mut kit_file = tokio::fs::File::open(&settings.file).await?;
     let mut contents = String::new();
     kit_file.read_to_string(&mut contents).await?;
 
@@ -1,18 +1,30 @@
 use std::path::Path;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use armake2::config::ConfigArray;
 use crate::context::AddonManager;
 use crate::create_handlebars;
+use super::util::config_string;
+use crate::identifier;
 use crate::BuildContext;
 use crate::Plugin;
 use crate::Result;
+use crate::terrain::{self, TerrainCatalogue, TerrainFile};
 use armake2::config::{Config, ConfigArrayElement, ConfigClass, ConfigEntry};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{RootSchema, Schema};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 const MISSION_SETTINGS_KEY: &str = "missions";
+const TERRAINS_KEY: &str = "terrains";
+const MISSION_SQM: &str = "mission.sqm";
+/// Magic bytes at the start of rapified (binarized) configs
+const RAPIFIED_MAGIC: &[u8; 4] = b"\0raP";
+/// Distance (in meters) the editor camera is placed above and south of the composition
+const CAMERA_DISTANCE: f32 = 50.;
 const CBA_SETTINGS: &str = "cba_settings_hasSettingsFile = 1;";
 
 const ON_PLAYER_DEATH: &str = "player setVariable [\"Saved_Loadout\",getUnitLoadout player];";
@@ -28,67 +40,89 @@ impl Plugin for MissionPlugin {
         // Extract MissionSettings from BuildContext
         let mission_settings = MissionSettings::from_build_config(&build_config)?;
 
-        // Load composition file
-        let composition = load_composition(
-            &mission_settings.composition,
-            mission_settings.composition_offset,
-            mission_settings.ignore_center
-        )
-        .await?;
+        // Load composition files
+        let terrains = TerrainCatalogue::load(mission_settings.terrains.as_deref()).await?;
+        let compositions = load_compositions(&mission_settings, &terrains).await?;
 
         // For each Map create mission based on settings.
-        let mut missions = create_missions(&mission_settings, &build_config).await?;
+        let mut missions = create_missions(&mission_settings, &build_config, &compositions).await?;
 
-        // Merge composition into mission
-        missions.iter_mut().for_each(|mission| {
-            if let Err(why) = mission.merge_composition(&composition) {
-                warn!("Failed to merge composition: {}", why);
+        let handlebars = create_handlebars()?;
+
+        // Merge compositions and slots into mission
+        for mission in missions.iter_mut() {
+            for composition in compositions.iter() {
+                mission.merge_composition(composition).map_err(|why| {
+                    format!(
+                        "Failed to merge composition {} into {}: {}",
+                        composition.path.display(),
+                        mission.mission_name(),
+                        why
+                    )
+                })?;
             }
-        });
+
+            if let Some(slots) = &mission_settings.slots {
+                mission
+                    .merge_slots(slots, &compositions, &handlebars)
+                    .map_err(|why| format!("Failed to add slots to {}: {}", mission.mission_name(), why))?;
+            }
+        }
 
         // Save mission to addon
         let mut addon_manager =
             AddonManager::from_context(&mission_settings.addon_name, build_config.clone());
 
+        let pbo_prefix = addon_manager.pbo_prefix();
+
+        let cba_settings = load_cba_settings(&mission_settings)?;
+        let description = match &mission_settings.settings {
+            Some(settings) => Some(render_description(&handlebars, &mission_settings, settings)?),
+            None => None,
+        };
+
         let classes = missions
             .into_iter()
-            .filter_map(|mission| {
+            .map(|mission| -> Result<MissionClass> {
                 let path: PathBuf =
-                    format!("missions/{}/mission.sqm", mission.mission_name()).into();
+                    format!("missions/{}/{}", mission.mission_name(), MISSION_SQM).into();
 
-                let sqm = match mission.to_sqm() {
-                    Ok(sqm) => sqm,
-                    Err(err) => {
-                        warn!("Error creating sqm: {}", err);
-                        return None;
-                    }
-                };
+                let sqm = mission
+                    .to_sqm()
+                    .map_err(|why| format!("Failed to create mission.sqm for {}: {}", mission.mission_name(), why))?;
 
-                addon_manager.add_file(sqm, path.clone());
+                // Hand-authored mission files
+                let mut mission_files = MissionFiles::from_settings(&mission_settings, &mission.map_name)
+                    .map_err(|why| format!("Failed to load mission files for {}: {}", mission.mission_name(), why))?;
 
+                addon_manager.add_file(sqm, path);
 
-                // CBA settings
-                if let Some(cba_settings_path) = &mission_settings.cba_settings_file  {
-                    if let Err(why) = add_cba_settings(&cba_settings_path, &mut addon_manager, &mission) {
-                        error!("Failed to add CBA Settings ({:?}) to addon: {}", &cba_settings_path, why);
-                        return None;
-                    }
-                }
+                // Any hand-authored mission.sqm was used as the base of the generated one
+                mission_files.take_path(MISSION_SQM);
+
+                // CBA settings and scenario attributes
+                add_description(&mut addon_manager, &mut mission_files, &mission, cba_settings.as_deref(), description.as_deref())
+                    .map_err(|why| format!("Failed to add description.ext to {}: {}", mission.mission_name(), why))?;
 
                 // Keep inventory on spawn
                 if mission_settings.respawn_keep_inventory {
-                    keep_inventory_on_respawn(&mut addon_manager, &mission);
+                    keep_inventory_on_respawn(&mut addon_manager, &mut mission_files, &mission)
+                        .map_err(|why| format!("Failed to add respawn scripts to {}: {}", mission.mission_name(), why))?;
                 }
 
-                Some((path, mission))
+                mission_files
+                    .add_to_addon(&mut addon_manager, &mission)
+                    .map_err(|why| format!("Failed to add mission files to {}: {}", mission.mission_name(), why))?;
+
+                Ok(MissionClass::new(&mission, &pbo_prefix, mission_settings.singleplayer))
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
 
         // Write config exposing Missions
         info!("Writing config.cpp...");
-        let handlebars = create_handlebars()?;
 
-        let addon = Addon::from_parts(build_config.prefix, mission_settings.addon_name, classes);
+        let required_addons = build_config.required_addons();
+        let addon = Addon::from_parts(build_config.prefix, mission_settings.addon_name, required_addons, classes);
         let config_cpp = handlebars.render("missions_addon", &addon)?;
 
         addon_manager.add_file(config_cpp, "config.cpp".into());
@@ -103,43 +137,326 @@ impl Plugin for MissionPlugin {
     fn name(&self) -> String {
         "missions".to_string()
     }
+
+    fn config_schema(&self, generator: &mut SchemaGenerator) -> Option<Schema> {
+        Some(generator.subschema_for::<MissionSettings>())
+    }
+
+    fn file_schemas(&self) -> Vec<(String, RootSchema)> {
+        vec![(TERRAINS_KEY.to_string(), SchemaGenerator::default().into_root_schema_for::<TerrainFile>())]
+    }
 }
 
-#[instrument(err, skip(addon_manager, mission))]
-fn add_cba_settings(cba_settings_path: &Path, addon_manager: &mut AddonManager, mission: &Mission) -> Result<()> {
-    addon_manager.add_file(CBA_SETTINGS.to_string(), "description.ext".into());
+/// CBA settings from `cba_settings_file`, followed by those from `[missions.cba_settings]`
+#[instrument(err, skip(mission_settings))]
+fn load_cba_settings(mission_settings: &MissionSettings) -> Result<Option<String>> {
+    let mut sections = Vec::new();
 
-    let mut cba_settings_string = String::new();
-    let mut settings_file = std::fs::File::open(cba_settings_path)?;
-    settings_file.read_to_string(&mut cba_settings_string)?;
+    if let Some(cba_settings_path) = &mission_settings.cba_settings_file {
+        let mut cba_settings_string = String::new();
+        let mut settings_file = std::fs::File::open(cba_settings_path)
+            .map_err(|why| format!("Failed to open CBA Settings ({:?}): {}", cba_settings_path, why))?;
+        settings_file.read_to_string(&mut cba_settings_string)?;
 
-    addon_manager.add_file(cba_settings_string, format!("missions/{}/cba_settings.sqf", mission.mission_name()).into());
-    addon_manager.add_file(CBA_SETTINGS.to_string(), format!("missions/{}/description.ext", mission.mission_name()).into());
+        sections.push(cba_settings_string.trim_end().to_string());
+    }
+
+    if !mission_settings.cba_settings.is_empty() {
+        sections.push(cba_settings_sqf(&mission_settings.cba_settings)?);
+    }
+
+    if sections.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("{}\n", sections.join("\n"))))
+    }
+}
+
+/// Add cba_settings.sqf and description.ext, which may both be needed by a mission
+#[instrument(err, skip(addon_manager, mission_files, mission, cba_settings, description))]
+fn add_description(
+    addon_manager: &mut AddonManager,
+    mission_files: &mut MissionFiles,
+    mission: &Mission,
+    cba_settings: Option<&str>,
+    description: Option<&str>,
+) -> Result<()> {
+    let mut description_ext = Vec::new();
+
+    if let Some(cba_settings) = cba_settings {
+        add_generated_file(addon_manager, mission_files, mission, "cba_settings.sqf", cba_settings)?;
+        description_ext.push(CBA_SETTINGS);
+    }
+
+    if let Some(description) = description {
+        description_ext.push(description.trim_end());
+    }
+
+    if !description_ext.is_empty() {
+        add_generated_file(addon_manager, mission_files, mission, "description.ext", &description_ext.join("\n"))?;
+    }
 
     Ok(())
 }
 
-fn keep_inventory_on_respawn(addon_manager: &mut AddonManager, mission: &Mission) {
-    addon_manager.add_file(ON_PLAYER_RESPAWN.to_string(), format!("missions/{}/onPlayerRespawn.sqf", mission.mission_name()).into());
-    addon_manager.add_file(ON_PLAYER_DEATH.to_string(), format!("missions/{}/onPlayerKilled.sqf", mission.mission_name()).into());
+fn keep_inventory_on_respawn(addon_manager: &mut AddonManager, mission_files: &mut MissionFiles, mission: &Mission) -> Result<()> {
+    add_generated_file(addon_manager, mission_files, mission, "onPlayerRespawn.sqf", ON_PLAYER_RESPAWN)?;
+    add_generated_file(addon_manager, mission_files, mission, "onPlayerKilled.sqf", ON_PLAYER_DEATH)?;
+
+    Ok(())
 }
 
-async fn add_mission_files(addon_manager: &mut AddonManager, mission_files_path: &PathBuf) -> Result<()> {
+/// Add a generated file to a mission. If the mission folder has a file with the same name, the
+/// generated contents are appended to it rather than replacing it.
+fn add_generated_file(
+    addon_manager: &mut AddonManager,
+    mission_files: &mut MissionFiles,
+    mission: &Mission,
+    file_name: &str,
+    generated: &str,
+) -> Result<()> {
+    let contents = match mission_files.take_text(file_name)? {
+        Some(template) if template.contains(generated) => template,
+        Some(template) => format!("{}\n{}\n", template.trim_end(), generated),
+        None => generated.to_string(),
+    };
+
+    addon_manager.add_file(contents, format!("missions/{}/{}", mission.mission_name(), file_name).into());
 
     Ok(())
 }
 
+/// Hand-authored files from `missions_folder` for a single mission.
+///
+/// Files in the root of `missions_folder` are added to every mission, and files in
+/// `missions_folder/<map>/` are only added to that map's mission, replacing root files with the same
+/// path.
+struct MissionFiles {
+    /// Source paths, keyed by their path relative to the mission folder
+    files: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl MissionFiles {
+    fn from_settings(mission_settings: &MissionSettings, map_name: &str) -> Result<Self> {
+        let mut files = BTreeMap::new();
+
+        let missions_folder = match &mission_settings.missions_folder {
+            Some(missions_folder) => missions_folder,
+            None => return Ok(MissionFiles { files }),
+        };
+
+        let is_map_folder = |name: &std::ffi::OsStr| {
+            mission_settings
+                .maps
+                .iter()
+                .any(|map| map.map_name().eq_ignore_ascii_case(&name.to_string_lossy()))
+        };
+
+        // Shared files, skipping per-map folders
+        let shared = walkdir::WalkDir::new(missions_folder)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| !(entry.depth() == 1 && entry.file_type().is_dir() && is_map_folder(entry.file_name())));
+
+        for entry in shared {
+            let entry = entry?;
+
+            if entry.file_type().is_file() {
+                files.insert(entry.path().strip_prefix(missions_folder)?.to_owned(), entry.path().to_owned());
+            }
+        }
+
+        // Per-map overrides
+        for entry in std::fs::read_dir(missions_folder)? {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() && entry.file_name().to_string_lossy().eq_ignore_ascii_case(map_name) {
+                let map_folder = entry.path();
+
+                for entry in walkdir::WalkDir::new(&map_folder).min_depth(1) {
+                    let entry = entry?;
+
+                    if entry.file_type().is_file() {
+                        files.insert(entry.path().strip_prefix(&map_folder)?.to_owned(), entry.path().to_owned());
+                    }
+                }
+            }
+        }
+
+        debug!("Mission files for {}: {:?}", map_name, files);
+
+        Ok(MissionFiles { files })
+    }
+
+    /// Remove a file from the mission files and return its source path
+    fn take_path(&mut self, file_name: &str) -> Option<PathBuf> {
+        let key = self
+            .files
+            .keys()
+            .find(|path| path.to_string_lossy().eq_ignore_ascii_case(file_name))
+            .cloned();
+
+        key.and_then(|key| self.files.remove(&key))
+    }
+
+    /// Remove a file from the mission files and return its contents, so it can be merged with
+    /// generated contents.
+    fn take_text(&mut self, file_name: &str) -> Result<Option<String>> {
+        match self.take_path(file_name) {
+            Some(path) => Ok(Some(std::fs::read_to_string(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn add_to_addon(self, addon_manager: &mut AddonManager, mission: &Mission) -> Result<()> {
+        for (relative, source) in self.files {
+            let mut folder: PathBuf = format!("missions/{}", mission.mission_name()).into();
+
+            if let Some(parent) = relative.parent() {
+                folder.push(parent);
+            }
+
+            addon_manager.add_asset(source, Some(folder))?;
+        }
+
+        Ok(())
+    }
+}
+
 type MapEntry = String;
 type MapOffsetEntry = (String, (f32, f32, f32));
-
-#[derive(Clone, Debug, Deserialize)]
+/// Map name, X, Y, Z position and rotation in degrees clockwise
+type MapRotatedEntry = (String, (f32, f32, f32), f32);
+/// Map name and a spawn area from the terrain catalogue, e.g. "spawn:airfield" or "center"
+type MapSpawnEntry = (String, String);
+/// Map name, spawn area and rotation in degrees clockwise
+type MapSpawnRotatedEntry = (String, String, f32);
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(untagged)]
 enum MapConfig {
     Map(MapEntry),
-    MapOffset(MapOffsetEntry)
+    MapOffset(MapOffsetEntry),
+    MapRotated(MapRotatedEntry),
+    MapSpawn(MapSpawnEntry),
+    MapSpawnRotated(MapSpawnRotatedEntry),
 }
 
-#[derive(Debug, Deserialize)]
+impl MapConfig {
+    fn map_name(&self) -> &str {
+        match self {
+            MapConfig::Map(map_name) => map_name,
+            MapConfig::MapOffset((map_name, _)) => map_name,
+            MapConfig::MapRotated((map_name, _, _)) => map_name,
+            MapConfig::MapSpawn((map_name, _)) => map_name,
+            MapConfig::MapSpawnRotated((map_name, _, _)) => map_name,
+        }
+    }
+
+    /// Placement of the mission's composition on this map, if it overrides the center and offset
+    fn placement(&self) -> Option<PlacementConfig> {
+        match self {
+            MapConfig::Map(_) => None,
+            MapConfig::MapOffset((_, position)) => Some(PlacementConfig::Position(*position)),
+            MapConfig::MapRotated((_, position, rotation)) => {
+                Some(PlacementConfig::Rotated((*position, *rotation)))
+            }
+            MapConfig::MapSpawn((_, spawn)) => Some(PlacementConfig::Spawn(spawn.clone())),
+            MapConfig::MapSpawnRotated((_, spawn, rotation)) => {
+                Some(PlacementConfig::SpawnRotated((spawn.clone(), *rotation)))
+            }
+        }
+    }
+}
+
+/// Position of a composition on a map, optionally rotated around the composition's center
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum PlacementConfig {
+    /// X, Y, Z position
+    Position((f32, f32, f32)),
+    /// X, Y, Z position and rotation in degrees clockwise
+    Rotated(((f32, f32, f32), f32)),
+    /// Spawn area from the terrain catalogue, e.g. "spawn:airfield" or "center"
+    Spawn(String),
+    /// Spawn area and rotation in degrees clockwise
+    SpawnRotated((String, f32)),
+}
+
+impl PlacementConfig {
+    /// Resolve spawn areas on a map using the terrain catalogue
+    fn resolve(&self, map_name: &str, terrains: &TerrainCatalogue) -> Result<Placement> {
+        let (spawn, rotation) = match self {
+            PlacementConfig::Position(position) => {
+                return Ok(Placement {
+                    position: *position,
+                    rotation: 0.,
+                })
+            }
+            PlacementConfig::Rotated((position, rotation)) => {
+                return Ok(Placement {
+                    position: *position,
+                    rotation: *rotation,
+                })
+            }
+            PlacementConfig::Spawn(spawn) => (spawn, 0.),
+            PlacementConfig::SpawnRotated((spawn, rotation)) => (spawn, *rotation),
+        };
+
+        let terrain = terrains
+            .get(map_name)
+            .ok_or_else(|| format!("{} is not in the terrain catalogue, can't place at \"{}\"", map_name, spawn))?;
+
+        let position = match spawn.strip_prefix(SPAWN_PREFIX) {
+            Some(name) => terrain.spawn(name)?,
+            None if spawn.eq_ignore_ascii_case(terrain::CENTER) => terrain.center(),
+            None => {
+                return Err(format!(
+                    "Invalid placement \"{}\" on {}, expected \"{}<name>\" or \"{}\"",
+                    spawn,
+                    map_name,
+                    SPAWN_PREFIX,
+                    terrain::CENTER
+                )
+                .into())
+            }
+        };
+
+        Ok(Placement { position, rotation })
+    }
+}
+
+const SPAWN_PREFIX: &str = "spawn:";
+
+/// Resolved position and rotation of a composition on a map
+#[derive(Clone, Copy, Debug)]
+struct Placement {
+    position: (f32, f32, f32),
+    rotation: f32,
+}
+
+/// Class names of the missions generated for the configured maps, for server mission rotations
+pub fn mission_class_names(build_config: &BuildContext) -> Result<Vec<String>> {
+    let mission_settings = MissionSettings::from_build_config(build_config)?;
+
+    Ok(mission_settings
+        .maps
+        .iter()
+        .map(|map| {
+            mission_class_name(
+                &build_config.prefix,
+                map.map_name(),
+                &mission_settings.mission_name,
+            )
+        })
+        .collect())
+}
+
+fn mission_class_name(prefix: &str, map_name: &str, mission_name: &str) -> String {
+    identifier::sanitize(&format!("{}_{}{}", prefix, map_name, mission_name))
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 struct MissionSettings {
     #[serde(default = "default_addon_name")]
     /// Name of the generated Addon
@@ -152,6 +469,18 @@ struct MissionSettings {
     #[serde(default = "default_mission_name")]
     mission_name: String,
 
+    /// Name shown in the mission list, `{map}` is replaced with the map name. Defaults to
+    /// "[prefix] mission_name (map)"
+    briefing_name: Option<String>,
+
+    /// Scenario game type (e.g. "Coop", "Zeus", "TDM")
+    #[serde(default = "default_game_type")]
+    game_type: String,
+
+    /// Also list the missions in the singleplayer scenario list
+    #[serde(default)]
+    singleplayer: bool,
+
     /// Delay, in seconds between death and when allowed to respawn.
     #[serde(default = "default_respawn_delay")]
     respawn_delay: usize,
@@ -161,32 +490,558 @@ struct MissionSettings {
     respawn_keep_inventory: bool, 
 
     /// Composition to add to missions
-    composition: PathBuf,
+    composition: Option<PathBuf>,
 
     #[serde(default)]
     /// X, Y, Z offset for the composition.
     composition_offset: (f32, f32, f32),
 
+    #[serde(default)]
     ignore_center: bool,
 
-    missions_folder: PathBuf,
-    cba_settings_file: Option<PathBuf>
+    /// Additional compositions to add to missions, each with their own placement
+    #[serde(default)]
+    compositions: Vec<CompositionConfig>,
+
+    /// Folder of hand-authored files (scripts, description.ext, briefings, images) to add to
+    /// every mission. Files in `<missions_folder>/<map>/` are only added to that map's mission.
+    missions_folder: Option<PathBuf>,
+
+    /// Terrain catalogue adding to, or overriding, the built-in terrain sizes and spawn areas
+    terrains: Option<PathBuf>,
+
+    /// Scenario attributes and mission parameters, written to description.ext
+    settings: Option<ScenarioSettings>,
+
+    /// Playable slots and Zeus game masters to add to every mission
+    slots: Option<SlotsConfig>,
+
+    /// `cba_settings.sqf` to add to every mission
+    cba_settings_file: Option<PathBuf>,
+
+    /// CBA settings to add to every mission, after any from `cba_settings_file`
+    #[serde(default)]
+    cba_settings: BTreeMap<String, CbaSetting>,
 }
 
 impl MissionSettings {
     pub fn from_build_config(build_config: &BuildContext) -> Result<MissionSettings> {
-        if let Some(mission_settings) = build_config.extra.get(MISSION_SETTINGS_KEY) {
-            let mission_settings: MissionSettings = mission_settings.clone().try_into()?;
-
-            Ok(mission_settings)
-        } else {
-            Err(format!(
-                "Failed to get field: {} from LAAT.toml",
-                MISSION_SETTINGS_KEY
+        build_config.plugin_config(MISSION_SETTINGS_KEY)
+    }
+
+    /// All compositions to add to missions, starting with `composition`, whose per-map
+    /// placement comes from `maps`.
+    fn compositions(&self) -> Vec<CompositionConfig> {
+        let mut compositions = Vec::new();
+
+        if let Some(path) = &self.composition {
+            compositions.push(CompositionConfig {
+                path: path.clone(),
+                offset: self.composition_offset,
+                ignore_center: self.ignore_center,
+                maps: self
+                    .maps
+                    .iter()
+                    .filter_map(|map| Some((map.map_name().to_string(), map.placement()?)))
+                    .collect(),
+            });
+        }
+
+        compositions.extend(self.compositions.iter().cloned());
+
+        compositions
+    }
+}
+
+/// Entry in `[[missions.compositions]]`
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct CompositionConfig {
+    /// Folder containing the composition's header.sqe and composition.sqe
+    path: PathBuf,
+
+    /// X, Y, Z offset for the composition.
+    #[serde(default)]
+    offset: (f32, f32, f32),
+
+    #[serde(default)]
+    ignore_center: bool,
+
+    /// Per-map X, Y, Z position, replacing the center and offset, optionally followed by a
+    /// rotation in degrees clockwise
+    #[serde(default)]
+    maps: HashMap<String, PlacementConfig>,
+}
+
+/// `[missions.settings]`, scenario attributes for mission.sqm and description.ext
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct ScenarioSettings {
+    #[serde(default)]
+    respawn: RespawnType,
+
+    /// Respawn templates, e.g. "MenuPosition", "Counter", "Tickets"
+    #[serde(default = "default_respawn_templates")]
+    respawn_templates: Vec<String>,
+
+    /// Disable AI in playable slots that no player has taken
+    #[serde(default = "default_disabled_ai")]
+    disabled_ai: bool,
+
+    #[serde(default = "default_min_players")]
+    min_players: u32,
+
+    max_players: Option<u32>,
+
+    /// Mission parameters, shown in the lobby
+    #[serde(default)]
+    params: Vec<ParamConfig>,
+}
+
+impl Default for ScenarioSettings {
+    fn default() -> Self {
+        ScenarioSettings {
+            respawn: RespawnType::default(),
+            respawn_templates: default_respawn_templates(),
+            disabled_ai: default_disabled_ai(),
+            min_players: default_min_players(),
+            max_players: None,
+            params: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum RespawnType {
+    None = 0,
+    Bird = 1,
+    Instant = 2,
+    #[default]
+    Base = 3,
+    Group = 4,
+    Side = 5,
+}
+
+impl RespawnType {
+    /// Value of `respawn` in mission.sqm and description.ext
+    fn value(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Entry in `[[missions.settings.params]]`
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct ParamConfig {
+    /// Class name of the parameter, used with `BIS_fnc_getParamValue`
+    name: String,
+    /// Title shown in the lobby
+    title: String,
+    values: Vec<i32>,
+    /// Text shown for each value, defaults to the values
+    texts: Option<Vec<String>>,
+    default: i32,
+}
+
+impl ParamConfig {
+    fn texts(&self) -> Result<Vec<String>> {
+        let texts = match &self.texts {
+            Some(texts) => texts.clone(),
+            None => self.values.iter().map(|value| value.to_string()).collect(),
+        };
+
+        if texts.len() != self.values.len() {
+            return Err(format!("Param {} has {} values but {} texts", self.name, self.values.len(), texts.len()).into());
+        }
+
+        if !identifier::is_valid(&self.name) {
+            return Err(format!(
+                "Param name {} is not a valid class name, try {}",
+                self.name,
+                identifier::sanitize(&self.name)
             )
-            .into())
+            .into());
+        }
+
+        if !self.values.contains(&self.default) {
+            return Err(format!("Param {} default {} is not one of its values", self.name, self.default).into());
+        }
+
+        Ok(texts)
+    }
+}
+
+/// A CBA setting, either just a value or a value with whether to force it on clients
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum CbaSetting {
+    Detailed {
+        #[schemars(with = "serde_json::Value")]
+        value: toml::Value,
+        #[serde(default)]
+        force: bool,
+    },
+    Value(#[schemars(with = "serde_json::Value")] toml::Value),
+}
+
+impl CbaSetting {
+    /// Line for this setting in cba_settings.sqf
+    fn to_sqf(&self, name: &str) -> Result<String> {
+        let (value, force) = match self {
+            CbaSetting::Detailed { value, force } => (value, *force),
+            CbaSetting::Value(value) => (value, false),
+        };
+
+        let force = if force { "force " } else { "" };
+
+        Ok(format!("{}{} = {};", force, name, sqf_value(value)?))
+    }
+}
+
+/// Format a TOML value as an SQF literal
+fn sqf_value(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(value) => format!("\"{}\"", config_string(value)),
+        toml::Value::Integer(value) => value.to_string(),
+        toml::Value::Float(value) => value.to_string(),
+        toml::Value::Boolean(value) => value.to_string(),
+        toml::Value::Array(values) => format!(
+            "[{}]",
+            values.iter().map(sqf_value).collect::<Result<Vec<_>>>()?.join(", ")
+        ),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => {
+            return Err(format!("Unsupported CBA setting value: {}", value).into())
         }
+    })
+}
+
+/// Generate cba_settings.sqf contents from `[missions.cba_settings]`
+fn cba_settings_sqf(settings: &BTreeMap<String, CbaSetting>) -> Result<String> {
+    let lines = settings
+        .iter()
+        .map(|(name, setting)| setting.to_sqf(name))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(lines.join("\n"))
+}
+
+/// Render description.ext from `[missions.settings]`
+fn render_description(
+    handlebars: &handlebars::Handlebars,
+    mission_settings: &MissionSettings,
+    settings: &ScenarioSettings,
+) -> Result<String> {
+    #[derive(Serialize)]
+    struct Param {
+        name: String,
+        title: String,
+        values: String,
+        texts: String,
+        default: i32,
     }
+
+    #[derive(Serialize)]
+    struct DescriptionTemplate {
+        respawn: u8,
+        respawn_delay: usize,
+        respawn_templates: String,
+        disabled_ai: u8,
+        game_type: String,
+        min_players: u32,
+        max_players: Option<u32>,
+        params: Vec<Param>,
+    }
+
+    let quote_all = |texts: &[String]| {
+        texts
+            .iter()
+            .map(|text| format!("\"{}\"", config_string(text)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let params = settings
+        .params
+        .iter()
+        .map(|param| {
+            Ok(Param {
+                name: param.name.clone(),
+                title: config_string(&param.title),
+                values: param.values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", "),
+                texts: quote_all(&param.texts()?),
+                default: param.default,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let template = DescriptionTemplate {
+        respawn: settings.respawn.value(),
+        respawn_delay: mission_settings.respawn_delay,
+        respawn_templates: quote_all(&settings.respawn_templates),
+        disabled_ai: settings.disabled_ai as u8,
+        game_type: config_string(&mission_settings.game_type),
+        min_players: settings.min_players,
+        max_players: settings.max_players,
+        params,
+    };
+
+    Ok(handlebars.render("missions_description", &template)?)
+}
+
+/// `[missions.slots]`, a roster of playable groups and Zeus slots
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct SlotsConfig {
+    /// Number of Zeus slots, each with its own game master module
+    #[serde(default)]
+    zeus: usize,
+
+    /// Unit class of Zeus slots
+    #[serde(default = "default_zeus_class")]
+    zeus_class: String,
+
+    /// X, Y, Z offset of the slots from the first composition's placement
+    #[serde(default)]
+    offset: (f32, f32, f32),
+
+    /// Make the first slot the player in the editor and singleplayer
+    #[serde(default)]
+    player: bool,
+
+    #[serde(default)]
+    groups: Vec<SlotGroupConfig>,
+}
+
+/// Entry in `[[missions.slots.groups]]`
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct SlotGroupConfig {
+    #[serde(default)]
+    side: Side,
+
+    /// Group name shown in the lobby, e.g. "Alpha 1-1"
+    name: Option<String>,
+
+    roles: Vec<RoleConfig>,
+}
+
+/// Entry in `[[missions.slots.groups.roles]]`
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct RoleConfig {
+    /// Unit class, e.g. "B_Soldier_SL_F"
+    class: String,
+
+    /// Role description shown in the lobby, defaults to the unit's name in the editor
+    description: Option<String>,
+
+    /// Variable name of the unit, when there is a single slot
+    name: Option<String>,
+
+    #[serde(default = "default_role_count")]
+    count: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema)]
+enum Side {
+    #[default]
+    West,
+    East,
+    Independent,
+    Civilian,
+    Logic,
+}
+
+/// Spacing (in meters) between generated slots
+const SLOT_SPACING: f32 = 2.;
+/// Curator module addons value for "All addons (including unofficial ones)"
+const CURATOR_ALL_ADDONS: u8 = 3;
+
+impl SlotsConfig {
+    /// Generate the roster as SQE `items`, with entity ids starting at 0 for `merge_entities` to
+    /// renumber. Slots are laid out in rows, one per group, starting at `origin`.
+    fn to_items(&self, handlebars: &handlebars::Handlebars, origin: (f32, f32, f32)) -> Result<EntryList> {
+        #[derive(Serialize)]
+        struct Unit {
+            x: f32,
+            altitude: f32,
+            y: f32,
+            side: Side,
+            flags: u8,
+            name: Option<String>,
+            description: String,
+            is_player: bool,
+            id: usize,
+            class: String,
+        }
+
+        #[derive(Serialize)]
+        struct Group {
+            item: usize,
+            side: Side,
+            /// Handlebars can't count arrays
+            unit_count: usize,
+            units: Vec<Unit>,
+            id: usize,
+        }
+
+        #[derive(Serialize)]
+        struct Curator {
+            item: usize,
+            x: f32,
+            altitude: f32,
+            y: f32,
+            id: usize,
+            owner: String,
+            name: String,
+            addons: u8,
+        }
+
+        #[derive(Serialize)]
+        struct SlotsTemplate {
+            items: usize,
+            groups: Vec<Group>,
+            curators: Vec<Curator>,
+        }
+
+        let (x, altitude, y) = origin;
+        let (offset_x, offset_altitude, offset_y) = self.offset;
+        let position = |row: usize, column: usize| {
+            (
+                x + offset_x + column as f32 * SLOT_SPACING,
+                altitude + offset_altitude,
+                y + offset_y - row as f32 * SLOT_SPACING,
+            )
+        };
+
+        let mut next_id = 0;
+        let mut id = || {
+            next_id += 1;
+            next_id - 1
+        };
+
+        let mut groups = Vec::new();
+        let mut curators = Vec::new();
+
+        // Zeus slots come first, so their variable names are stable
+        if self.zeus > 0 {
+            let group_id = id();
+            let mut units = Vec::new();
+
+            for index in 0..self.zeus {
+                let (x, altitude, y) = position(0, index);
+                let name = format!("zeus_{}", index + 1);
+
+                units.push(Unit {
+                    x,
+                    altitude,
+                    y,
+                    side: Side::Logic,
+                    flags: if index == 0 { 7 } else { 5 },
+                    description: config_string(&format!("Zeus {}@Zeus", index + 1)),
+                    is_player: self.player && index == 0,
+                    id: id(),
+                    class: config_string(&self.zeus_class),
+                    name: Some(name),
+                });
+            }
+
+            groups.push(Group {
+                item: 0,
+                side: Side::Logic,
+                unit_count: units.len(),
+                units,
+                id: group_id,
+            });
+        }
+
+        for group in self.groups.iter() {
+            let row = groups.len();
+            let group_id = id();
+            let mut units = Vec::new();
+
+            for role in group.roles.iter() {
+                if role.name.is_some() && role.count != 1 {
+                    return Err(format!("Role {} has a variable name, but {} slots", role.class, role.count).into());
+                }
+
+                for _ in 0..role.count {
+                    let (x, altitude, y) = position(row, units.len());
+                    let description = role.description.clone().unwrap_or_default();
+                    let description = match &group.name {
+                        Some(name) => format!("{}@{}", description, name),
+                        None => description,
+                    };
+
+                    units.push(Unit {
+                        x,
+                        altitude,
+                        y,
+                        side: group.side,
+                        flags: if units.is_empty() { 7 } else { 5 },
+                        description: config_string(&description),
+                        is_player: self.player && groups.is_empty() && units.is_empty(),
+                        id: id(),
+                        class: config_string(&role.class),
+                        name: role.name.as_deref().map(config_string),
+                    });
+                }
+            }
+
+            groups.push(Group {
+                item: groups.len(),
+                side: group.side,
+                unit_count: units.len(),
+                units,
+                id: group_id,
+            });
+        }
+
+        // One game master module per Zeus slot, owned through the slot's variable name
+        for index in 0..self.zeus {
+            let (x, altitude, y) = position(groups.len(), index);
+
+            curators.push(Curator {
+                item: groups.len() + index,
+                x,
+                altitude,
+                y,
+                id: id(),
+                owner: format!("zeus_{}", index + 1),
+                name: format!("Zeus {}", index + 1),
+                addons: CURATOR_ALL_ADDONS,
+            });
+        }
+
+        let template = SlotsTemplate {
+            items: groups.len() + curators.len(),
+            groups,
+            curators,
+        };
+
+        let sqe = handlebars.render("missions_slots", &template)?;
+        let config = Config::read(&mut sqe.as_bytes(), None, &Vec::new())?;
+
+        match config.into_inner().entries.unwrap_or_default().into_iter().next() {
+            Some((_, ConfigEntry::ClassEntry(items))) => Ok(items.entries.unwrap_or_default()),
+            _ => Err("Failed to generate slots".into()),
+        }
+    }
+}
+
+fn default_zeus_class() -> String {
+    "VirtualCurator_F".to_string()
+}
+
+fn default_role_count() -> usize {
+    1
+}
+
+fn default_respawn_templates() -> Vec<String> {
+    vec!["MenuPosition".to_string(), "Counter".to_string()]
+}
+
+fn default_disabled_ai() -> bool {
+    true
+}
+
+fn default_min_players() -> u32 {
+    1
 }
 
 fn default_addon_name() -> String {
@@ -197,15 +1052,22 @@ fn default_mission_name() -> String {
     "ZeusMission".to_string()
 }
 
+fn default_game_type() -> String {
+    "Coop".to_string()
+}
+
 fn default_respawn_delay() -> usize {
     2
 }
 
 struct Composition {
+    path: PathBuf,
     header: Config,
     composition: Config,
     offset: (f32, f32, f32),
-    ignore_center: bool
+    ignore_center: bool,
+    /// Per-map placement, keyed by lowercase map name
+    placements: HashMap<String, Placement>,
 }
 
 impl Composition {
@@ -216,14 +1078,16 @@ impl Composition {
             tokio::fs::File::open(format!("{}/composition.sqe", path.display()))
         );
 
-        let header = Config::read(&mut header?.into_std().await, None, &Vec::new())?;
-        let composition = Config::read(&mut composition?.into_std().await, None, &Vec::new())?;
+        let header = read_config(&mut header?.into_std().await)?;
+        let composition = read_config(&mut composition?.into_std().await)?;
 
         Ok(Composition {
+            path: path.clone(),
             header,
             composition,
             offset,
-            ignore_center
+            ignore_center,
+            placements: HashMap::new(),
         })
     }
 
@@ -231,26 +1095,25 @@ impl Composition {
         self.offset = offset;
     }
 
+    /// Place the composition on `map_name`, instead of at its center and offset
+    fn set_placement(&mut self, map_name: &str, placement: Placement) {
+        self.placements.insert(map_name.to_lowercase(), placement);
+    }
+
     /// Get "center[]" from SQE, cast it into a tuple
     pub fn get_center(&self) -> Result<(f32, f32, f32)> {
-        let config = self.composition.inner();
+        if let Some(ConfigEntry::ArrayEntry(array)) = self.composition.get("center") {
+            debug!("Center Array: {:?}", array);
 
-        if let Some(entries) = config.entries.clone() {
-            let map: HashMap<String, ConfigEntry> = entries.into_iter().collect();
+            let center = get_center_from_field(array);
 
-            if let Some(ConfigEntry::ArrayEntry(array)) = map.get("center") {
-                debug!("Center Array: {:?}", array);
-
-                let center = get_center_from_field(&array);
-
-                if !self.ignore_center {
-                    return Ok(center);
-                }
-                else {
-                    return Ok((0., 0., 0.));
-                }
-            };
-        }
+            if !self.ignore_center {
+                return Ok(center);
+            }
+            else {
+                return Ok((0., 0., 0.));
+            }
+        };
 
         Err("Failed to get center[]".into())
     }
@@ -259,34 +1122,126 @@ impl Composition {
         let (x1, y1, z1) = self.get_center()?;
         let (x2, y2, z2) = self.offset;
 
-        Ok((x1 + x2, y1 + y2, z1 + z2))
+        Ok((x1 + x2, y1 + y2, z1 + z2))
+    }
+
+    /// Get where the composition is placed on a map, using the map's placement instead of the
+    /// center and offset if there is one
+    pub fn get_placement(&self, map_name: &str) -> Result<(f32, f32, f32)> {
+        if let Some(placement) = self.placements.get(&map_name.to_lowercase()) {
+            info!("Overriding offset...");
+            Ok(placement.position)
+        }
+        else {
+            self.get_offset()
+        }
+    }
+
+    /// Get the rotation of the composition on a map, in degrees clockwise
+    pub fn get_rotation(&self, map_name: &str) -> f32 {
+        self.placements
+            .get(&map_name.to_lowercase())
+            .map(|placement| placement.rotation)
+            .unwrap_or_default()
+    }
+
+    /// Get, rotate and offset items from the SQE
+    pub fn get_offseted_items(&self, map_name: &str) -> Result<EntryList> {
+        let offset = self.get_placement(map_name)?;
+        let rotation = self.get_rotation(map_name);
+
+        if let Some(items) = self.composition.class("items") {
+            debug!("Item Classes: {}", items.entries().len());
+            return Ok(offset_classes(items.entries().to_vec(), offset, rotation));
+        }
+
+        Err("Failed to get offseted items".into())
+    }
+
+    /// Link classes between the composition's entities, like sync lines, which refer to them by id
+    pub fn get_links(&self) -> EntryList {
+        self.composition
+            .class("connections/Links")
+            .map(|links| links.entries().to_vec())
+            .unwrap_or_default()
+    }
+}
+
+/// Read a config (SQM, SQE) that may either be text or rapified (binarized)
+fn read_config<I: Read + Seek>(input: &mut I) -> Result<Config> {
+    let mut magic = [0; 4];
+    let is_rapified = match input.read_exact(&mut magic) {
+        Ok(()) => &magic == RAPIFIED_MAGIC,
+        Err(why) if why.kind() == std::io::ErrorKind::UnexpectedEof => false,
+        Err(why) => return Err(why.into()),
+    };
+
+    input.seek(SeekFrom::Start(0))?;
+
+    if is_rapified {
+        debug!("Reading rapified config");
+        Ok(Config::read_rapified(input)?)
+    } else {
+        Ok(Config::read(input, None, &Vec::new())?)
+    }
+}
+
+/// Copy an Eden mission folder (`<name>.<map>`) or mission.sqm into the per-map folder of
+/// `missions_folder`, derapifying mission.sqm so it can be diffed and edited. Returns the folder
+/// the mission was imported to.
+#[instrument(err, skip(build_config))]
+pub fn import_mission(build_config: &BuildContext, path: &Path, map: Option<String>) -> Result<PathBuf> {
+    let mission_settings = MissionSettings::from_build_config(build_config)?;
+
+    let missions_folder = mission_settings
+        .missions_folder
+        .as_ref()
+        .ok_or("Set missions_folder in the [missions] section to import missions")?;
+
+    let (mission_folder, sqm_path) = if path.is_dir() {
+        (Some(path), path.join(MISSION_SQM))
+    } else {
+        (None, path.to_owned())
+    };
+
+    // Eden saves missions as `<name>.<map>`
+    let map_name = match map {
+        Some(map) => map,
+        None => mission_folder
+            .and_then(|folder| folder.extension())
+            .map(|map| map.to_string_lossy().to_string())
+            .ok_or_else(|| format!("Can't tell the map of {}, use --map", path.display()))?,
+    };
+
+    if !mission_settings.maps.iter().any(|map| map.map_name().eq_ignore_ascii_case(&map_name)) {
+        warn!("{} is not in the [missions] maps, the mission won't be built", map_name);
     }
 
-    /// Get and offset items from the SQE
-    pub fn get_offseted_items(&self, offset_override: Option<(f32, f32, f32)>) -> Result<EntryList> {
-        let offset = if let Some(offset_override) = offset_override {
-            info!("Overriding offset...");
-            offset_override
+    let destination = missions_folder.join(&map_name);
+    std::fs::create_dir_all(&destination)?;
+
+    if let Some(mission_folder) = mission_folder {
+        for entry in walkdir::WalkDir::new(mission_folder).min_depth(1) {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(mission_folder)?;
+
+            if entry.file_type().is_dir() {
+                std::fs::create_dir_all(destination.join(relative))?;
+            } else if !relative.to_string_lossy().eq_ignore_ascii_case(MISSION_SQM) {
+                std::fs::copy(entry.path(), destination.join(relative))?;
+            }
         }
-        else {
-            self.get_offset()?
-        };
+    }
 
-        let config = self.composition.inner();
+    let sqm = read_config(&mut std::fs::File::open(&sqm_path)?)
+        .map_err(|why| format!("Failed to read {}: {}", sqm_path.display(), why))?;
 
-        if let Some(entries) = config.entries.clone() {
-            let map: HashMap<String, ConfigEntry> = entries.into_iter().collect();
+    let mut output = std::fs::File::create(destination.join(MISSION_SQM))?;
+    sqm.write(&mut output)?;
 
-            if let Some(ConfigEntry::ClassEntry(items)) = map.get("items") {
-                if let Some(entries) = items.entries.clone() {
-                    debug!("Item Classes: {}", entries.len());
-                    return Ok(offset_classes(entries, offset));
-                }
-            };
-        }
+    info!("Imported {} to {}", path.display(), destination.display());
 
-        Err("Failed to get offseted items".into())
-    }
+    Ok(destination)
 }
 
 fn get_center_from_field(array: &ConfigArray) -> (f32, f32, f32) {
@@ -294,6 +1249,7 @@ fn get_center_from_field(array: &ConfigArray) -> (f32, f32, f32) {
         match x {
             ConfigArrayElement::FloatElement(x) => *x,
             ConfigArrayElement::IntElement(x) => *x as f32,
+            ConfigArrayElement::Int64Element(x) => *x as f32,
             _ => 0.
         }
     };
@@ -307,9 +1263,10 @@ fn get_center_from_field(array: &ConfigArray) -> (f32, f32, f32) {
 
 type EntryList = Vec<(String, ConfigEntry)>;
 
-/// Offset classes recursively
+/// Rotate classes recursively around the composition's center by `rotation` degrees clockwise,
+/// then offset them
 #[instrument(skip(entries, composition_offset))]
-fn offset_classes(entries: EntryList, composition_offset: (f32, f32, f32)) -> EntryList {
+fn offset_classes(entries: EntryList, composition_offset: (f32, f32, f32), rotation: f32) -> EntryList {
     let offsets = [
         composition_offset.0,
         composition_offset.1,
@@ -320,24 +1277,31 @@ fn offset_classes(entries: EntryList, composition_offset: (f32, f32, f32)) -> En
         .into_iter()
         .map(|(name, entry)| {
             let entry = if let ConfigEntry::ClassEntry(mut class) = entry {
-                    // Offset
+                    // Rotate and offset
                     class.entries = class.entries.map(|mut entries| {
-                        entries.iter_mut().find(|(name, _)| name == "position").map(
-                            |(name, entry)| {
-                                if let ConfigEntry::ArrayEntry(position) = entry {
+                        for (name, entry) in entries.iter_mut() {
+                            match (name.to_lowercase().as_str(), entry) {
+                                ("position", ConfigEntry::ArrayEntry(position)) => {
+                                    rotate_position(position, rotation);
+
                                     position.elements = position
                                         .elements
                                         .iter_mut()
                                         .enumerate()
-                                        .map(|(idx, el)| add_to_element(el.clone(), offsets[idx]))
+                                        .map(|(idx, el)| add_to_element(el.clone(), offsets.get(idx).copied().unwrap_or_default()))
                                         .collect();
-
-                                    (name, entry)
-                                } else {
-                                    (name, entry)
                                 }
-                            },
-                        );
+                                // Entity yaw in radians, as {pitch, yaw, bank}
+                                ("angles", ConfigEntry::ArrayEntry(angles)) if rotation != 0. => {
+                                    if let Some(yaw) = angles.elements.get_mut(1) {
+                                        *yaw = add_angle(yaw.clone(), rotation.to_radians(), std::f32::consts::TAU);
+                                    }
+                                }
+                                // Marker and trigger directions in degrees
+                                ("dir", entry) | ("angle", entry) if rotation != 0. => rotate_angle_entry(entry, rotation),
+                                _ => {}
+                            }
+                        }
 
                         entries
                     });
@@ -345,7 +1309,7 @@ fn offset_classes(entries: EntryList, composition_offset: (f32, f32, f32)) -> En
                     // Recurse
                     class.entries = class
                         .entries
-                        .map(|entries| offset_classes(entries, composition_offset));
+                        .map(|entries| offset_classes(entries, composition_offset, rotation));
 
                 ConfigEntry::ClassEntry(class)
             } else {
@@ -357,6 +1321,179 @@ fn offset_classes(entries: EntryList, composition_offset: (f32, f32, f32)) -> En
         .collect()
 }
 
+/// Rotate an SQE `{x, altitude, y}` position around the origin by `rotation` degrees clockwise
+fn rotate_position(position: &mut ConfigArray, rotation: f32) {
+    if rotation == 0. || position.elements.len() < 3 {
+        return;
+    }
+
+    let (x, _, y) = get_center_from_field(position);
+    let (sin, cos) = rotation.to_radians().sin_cos();
+
+    position.elements[0] = ConfigArrayElement::FloatElement(x * cos + y * sin);
+    position.elements[2] = ConfigArrayElement::FloatElement(y * cos - x * sin);
+}
+
+/// Add to an angle element, keeping it within `0..full_turn`
+fn add_angle(element: ConfigArrayElement, increment: f32, full_turn: f32) -> ConfigArrayElement {
+    match add_to_element(element, increment) {
+        ConfigArrayElement::FloatElement(angle) => ConfigArrayElement::FloatElement(angle.rem_euclid(full_turn)),
+        element => element,
+    }
+}
+
+/// Add to a numeric angle entry in degrees, keeping it within `0..360`
+fn rotate_angle_entry(entry: &mut ConfigEntry, increment: f32) {
+    let angle = match entry {
+        ConfigEntry::FloatEntry(angle) => *angle,
+        ConfigEntry::IntEntry(angle) => *angle as f32,
+        ConfigEntry::Int64Entry(angle) => *angle as f32,
+        _ => return,
+    };
+
+    *entry = ConfigEntry::FloatEntry((angle + increment).rem_euclid(360.));
+}
+
+/// Append entity `Item#` classes to `Mission.Entities` in an SQM's root entries, and the link
+/// `Item#` classes between them (sync, crew) to `Mission.Connections.Links`. Appended items are
+/// renumbered after the existing ones, and their entity `id`s, along with the `item0`/`item1`
+/// ids links refer to, are moved past the highest id in the mission, which is then stored in
+/// `ItemIDProvider.nextID`.
+fn merge_entities(root: &mut ConfigClass, mut items: EntryList, mut links: EntryList) -> Result<()> {
+    let next_id = max_id(root.entries()).map(|id| id + 1).unwrap_or(0);
+    offset_ids(&mut items, next_id);
+    offset_link_ids(&mut links, next_id);
+
+    let entities = root.class_or_insert("Mission/Entities")?;
+    let mut count = entities.entries().iter().filter(|(name, entry)| is_item_class(name, entry)).count();
+
+    let entries = entities.entries.get_or_insert_with(Vec::new);
+
+    for (name, entry) in items {
+        if is_item_class(&name, &entry) {
+            entries.push((format!("Item{}", count), entry));
+            count += 1;
+        }
+    }
+
+    set_int_entry(entities, "items", count as i32);
+
+    let next_id = max_id(root.entries()).map(|id| id + 1).unwrap_or(0);
+    set_int_entry(root.class_or_insert("ItemIDProvider")?, "nextID", next_id);
+
+    merge_links(root, links)
+}
+
+/// Append link `Item#` classes to `Mission.Connections.Links`, renumbered after the existing ones,
+/// with `linkID`s past the highest one in the mission, which is then stored in
+/// `LinkIDProvider.nextID`
+fn merge_links(root: &mut ConfigClass, links: EntryList) -> Result<()> {
+    if !links.iter().any(|(name, entry)| is_item_class(name, entry)) {
+        return Ok(());
+    }
+
+    let connections = root.class_or_insert("Mission/Connections")?;
+    let existing = connections.class_or_insert("Links")?;
+
+    let mut count = existing.entries().iter().filter(|(name, entry)| is_item_class(name, entry)).count();
+    let mut next_link_id = existing
+        .entries()
+        .iter()
+        .filter_map(|(_, entry)| match entry {
+            ConfigEntry::ClassEntry(link) => match link.get("linkID") {
+                Some(ConfigEntry::IntEntry(id)) => Some(*id + 1),
+                _ => None,
+            },
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let entries = existing.entries.get_or_insert_with(Vec::new);
+
+    for (name, entry) in links {
+        if !is_item_class(&name, &entry) {
+            continue;
+        }
+
+        if let ConfigEntry::ClassEntry(mut link) = entry {
+            set_int_entry(&mut link, "linkID", next_link_id);
+            entries.push((format!("Item{}", count), ConfigEntry::ClassEntry(link)));
+            next_link_id += 1;
+            count += 1;
+        }
+    }
+
+    set_int_entry(existing, "items", count as i32);
+    set_int_entry(connections.class_or_insert("LinkIDProvider")?, "nextID", next_link_id);
+
+    Ok(())
+}
+
+fn is_item_class(name: &str, entry: &ConfigEntry) -> bool {
+    name.to_lowercase().starts_with("item") && matches!(entry, ConfigEntry::ClassEntry(_))
+}
+
+/// Set an int entry, inserting it at the start of the class if it doesn't exist
+fn set_int_entry(class: &mut ConfigClass, name: &str, value: i32) {
+    match class.get_mut(name) {
+        Some(entry) => *entry = ConfigEntry::IntEntry(value),
+        None => class
+            .entries
+            .get_or_insert_with(Vec::new)
+            .insert(0, (name.to_string(), ConfigEntry::IntEntry(value))),
+    }
+}
+
+/// Highest entity `id` in the entries, recursively
+fn max_id(entries: &[(String, ConfigEntry)]) -> Option<i32> {
+    entries
+        .iter()
+        .filter_map(|(name, entry)| match entry {
+            ConfigEntry::IntEntry(id) if name.eq_ignore_ascii_case("id") => Some(*id),
+            ConfigEntry::ClassEntry(class) => max_id(class.entries()),
+            _ => None,
+        })
+        .max()
+}
+
+/// Add `offset` to every entity `id` in the entries, and to the entity ids in `Links` classes,
+/// recursively
+fn offset_ids(entries: &mut EntryList, offset: i32) {
+    for (name, entry) in entries.iter_mut() {
+        match entry {
+            ConfigEntry::IntEntry(id) if name.eq_ignore_ascii_case("id") => *id += offset,
+            ConfigEntry::ClassEntry(class) if name.eq_ignore_ascii_case("Links") => {
+                if let Some(links) = class.entries.as_mut() {
+                    offset_link_ids(links, offset);
+                }
+            }
+            ConfigEntry::ClassEntry(class) => {
+                if let Some(entries) = class.entries.as_mut() {
+                    offset_ids(entries, offset);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Add `offset` to the `item0`/`item1` entity ids of link `Item#` classes
+fn offset_link_ids(links: &mut EntryList, offset: i32) {
+    for (_, link) in links.iter_mut() {
+        if let ConfigEntry::ClassEntry(link) = link {
+            for (name, entry) in link.entries.iter_mut().flatten() {
+                match entry {
+                    ConfigEntry::IntEntry(id) if name.eq_ignore_ascii_case("item0") || name.eq_ignore_ascii_case("item1") => {
+                        *id += offset
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
 fn add_to_element(element: ConfigArrayElement, increment: f32) -> ConfigArrayElement {
     match element {
         ConfigArrayElement::StringElement(_) => {}
@@ -366,136 +1503,223 @@ fn add_to_element(element: ConfigArrayElement, increment: f32) -> ConfigArrayEle
         ConfigArrayElement::IntElement(int) => {
             return ConfigArrayElement::FloatElement(int as f32 + increment);
         }
+        ConfigArrayElement::Int64Element(int) => {
+            return ConfigArrayElement::FloatElement(int as f32 + increment);
+        }
         ConfigArrayElement::ArrayElement(_) => {}
     }
 
     element
 }
 
-#[instrument(err)]
-async fn load_composition(
-    composition_path: &PathBuf,
-    composition_offset: (f32, f32, f32),
-    ignore_center: bool
-) -> Result<Composition> {
-    info!("Loading composition at: {:?}", composition_path);
-    Ok(Composition::from_path(composition_path, composition_offset, ignore_center).await?)
+#[instrument(err, skip(mission_settings, terrains))]
+async fn load_compositions(mission_settings: &MissionSettings, terrains: &TerrainCatalogue) -> Result<Vec<Composition>> {
+    let mut compositions = Vec::new();
+
+    for config in mission_settings.compositions() {
+        info!("Loading composition at: {:?}", config.path);
+        let mut composition = Composition::from_path(&config.path, config.offset, config.ignore_center).await?;
+
+        place_composition(&mut composition, &config, terrains)?;
+
+        compositions.push(composition);
+    }
+
+    check_placements(mission_settings, terrains, &compositions)?;
+
+    Ok(compositions)
+}
+
+/// Set the per-map placements of a composition. Maps without a placement of their own keep the
+/// composition's center and offset.
+fn place_composition(composition: &mut Composition, config: &CompositionConfig, terrains: &TerrainCatalogue) -> Result<()> {
+    for (map_name, placement) in config.maps.iter() {
+        let placement = placement
+            .resolve(map_name, terrains)
+            .map_err(|why| format!("Composition {:?}: {}", config.path, why))?;
+        composition.set_placement(map_name, placement);
+    }
+
+    Ok(())
+}
+
+/// Fail if any composition is placed outside of a catalogued map
+fn check_placements(mission_settings: &MissionSettings, terrains: &TerrainCatalogue, compositions: &[Composition]) -> Result<()> {
+    for map in mission_settings.maps.iter() {
+        let terrain = match terrains.get(map.map_name()) {
+            Some(terrain) => terrain,
+            None => {
+                debug!("{} is not in the terrain catalogue, skipping bounds check", map.map_name());
+                continue;
+            }
+        };
+
+        for composition in compositions {
+            let position = composition.get_placement(map.map_name())?;
+
+            terrain
+                .check_bounds(position)
+                .map_err(|why| format!("Composition {:?}: {}", composition.path, why))?;
+        }
+    }
+
+    Ok(())
 }
 
-#[instrument(err)]
+#[instrument(err, skip(compositions))]
 async fn create_missions(
     mission_settings: &MissionSettings,
     build_config: &BuildContext,
+    compositions: &[Composition],
 ) -> Result<Vec<Mission>> {
     info!("Creating missions...");
-    Ok(mission_settings
+    mission_settings
         .maps
         .iter()
-        .filter_map(|map| {
+        .map(|map| {
             Mission::new(
                 build_config.prefix.clone(),
                 mission_settings.mission_name.clone(),
                 map.clone(),
-                &mission_settings,
-                &build_config,
+                mission_settings,
+                build_config,
+                compositions,
             )
-            .ok()
+            .map_err(|why| format!("Failed to create mission for {}: {}", map.map_name(), why).into())
         })
-        .collect())
+        .collect()
 }
 
 struct Mission {
     map_name: String,
     mission_name: String,
     prefix: String,
-    offset_override: Option<(f32, f32, f32)>,
+    briefing_name: String,
 
     sqm: Config,
 }
 
 impl Mission {
-    #[instrument(skip(mission_settings), err)]
+    #[instrument(skip(mission_settings, compositions), err)]
     pub fn new(
         prefix: String,
         mission_name: String,
         map: MapConfig,
         mission_settings: &MissionSettings,
         build_config: &BuildContext,
+        compositions: &[Composition],
     ) -> Result<Self> {
         let handlebars = create_handlebars()?;
 
-        let (map_name, offset_override) = match map {
-            MapConfig::Map(map_name) => (map_name, None),
-            MapConfig::MapOffset((map_name, offset)) => (map_name, Some(offset)),
+        let map_name = map.map_name().to_string();
+
+        let class_name = mission_class_name(&prefix, &map_name, &mission_name);
+
+        let briefing_name = match &mission_settings.briefing_name {
+            Some(briefing_name) => briefing_name.replace("{map}", &map_name),
+            None => format!("[{}] {} ({})", prefix, mission_name, map_name),
         };
 
         #[derive(Serialize)]
         struct MissionTemplate {
             author: String,
+            respawn: u8,
             respawn_delay: usize,
-            mission_name: String,
+            /// Handlebars can't count arrays
+            respawn_template_count: usize,
+            respawn_templates: Vec<String>,
+            disabled_ai: u8,
+            min_players: u32,
+            max_players: Option<u32>,
+            source_name: String,
+            briefing_name: String,
+            game_type: String,
+            random_seed: u32,
+            camera_x: f32,
+            camera_y: f32,
+            camera_z: f32,
         }
 
+        // Look down at the first composition from the south
+        let (x, y, z) = match compositions.first() {
+            Some(composition) => composition.get_placement(&map_name)?,
+            None => (0., 0., 0.),
+        };
+
+        let settings = mission_settings.settings.clone().unwrap_or_default();
+
         let template = MissionTemplate {
             author: build_config
                 .extra
                 .get("author")
-                .map(|v| v.to_string())
+                .and_then(|v| v.as_str())
+                .map(config_string)
                 .unwrap_or_default(),
-            mission_name: mission_name.clone(),
+            source_name: class_name.clone(),
+            briefing_name: config_string(&briefing_name),
+            game_type: config_string(&mission_settings.game_type),
+            respawn: settings.respawn.value(),
             respawn_delay: mission_settings.respawn_delay,
+            respawn_template_count: settings.respawn_templates.len(),
+            respawn_templates: settings.respawn_templates.iter().map(|name| config_string(name)).collect(),
+            disabled_ai: settings.disabled_ai as u8,
+            min_players: settings.min_players,
+            max_players: settings.max_players,
+            random_seed: random_seed(&class_name),
+            camera_x: x,
+            camera_y: y + CAMERA_DISTANCE,
+            camera_z: z - CAMERA_DISTANCE,
         };
 
-        let sqm = handlebars.render("mission.sqm", &template)?;
+        // A hand-authored mission.sqm replaces the template
+        let template_path = MissionFiles::from_settings(mission_settings, &map_name)?.take_path(MISSION_SQM);
 
-        let config = Config::read(&mut sqm.as_bytes(), None, &Vec::new())?;
+        let config = match template_path {
+            Some(template_path) => {
+                info!("Using mission template: {}", template_path.display());
+                read_config(&mut std::fs::File::open(&template_path)?)
+                    .map_err(|why| format!("Failed to read {}: {}", template_path.display(), why))?
+            }
+            None => {
+                let sqm = handlebars.render("mission.sqm", &template)?;
+                Config::read(&mut sqm.as_bytes(), None, &Vec::new())?
+            }
+        };
 
         Ok(Mission {
             map_name,
-            offset_override,
             mission_name,
             prefix,
+            briefing_name,
             sqm: config,
         })
     }
 
+    /// Append the composition's entities to `Mission.Entities`
     #[instrument(skip(self, composition))]
     pub fn merge_composition(&mut self, composition: &Composition) -> Result<()> {
-        let items = composition.get_offseted_items(self.offset_override)?;
+        let items = composition.get_offseted_items(&self.map_name)?;
 
-        let mut class = self.sqm.inner_mut();
-
-        // Mission.Entities = items
-
-        class.entries = class.entries.clone().map(|entries| {
-            entries.into_iter().map(|(name, config)| {
-                if name == "Mission" {
-                    if let ConfigEntry::ClassEntry(mut mission) = config {
-                        let parent = mission.parent.clone();
-
-                        mission.entries = mission.entries.map(|entries| {
-                            let mut map: HashMap<String, ConfigEntry> = entries.into_iter().collect();
-                            let entities = ConfigEntry::ClassEntry(ConfigClass {
-                                parent,
-                                is_external: false,
-                                is_deletion: false,
-                                entries: Some(items.clone())
-                            });
-
-                            map.insert("Entities".to_string(), entities);
-
-                            map.into_iter().collect()
-                        });
+        merge_entities(self.sqm.inner_mut(), items, composition.get_links())
+    }
 
-                        return (name, ConfigEntry::ClassEntry(mission));
-                    }
-                }
+    /// Append the slot roster's groups and Zeus modules to `Mission.Entities`, placed relative
+    /// to the first composition
+    #[instrument(skip(self, slots, compositions, handlebars))]
+    pub fn merge_slots(
+        &mut self,
+        slots: &SlotsConfig,
+        compositions: &[Composition],
+        handlebars: &handlebars::Handlebars,
+    ) -> Result<()> {
+        let origin = match compositions.first() {
+            Some(composition) => composition.get_placement(&self.map_name)?,
+            None => (0., 0., 0.),
+        };
 
-                (name, config)
-            }).collect()
-        });
+        let items = slots.to_items(handlebars, origin)?;
 
-        Ok(())
+        merge_entities(self.sqm.inner_mut(), items, Vec::new())
     }
 
     /// Convert this mission to SQM
@@ -512,7 +1736,7 @@ impl Mission {
     }
 
     pub fn class_name(&self) -> String {
-        format!("{}_{}{}", self.prefix, self.map_name, self.mission_name,)
+        mission_class_name(&self.prefix, &self.map_name, &self.mission_name)
     }
 }
 
@@ -520,6 +1744,7 @@ impl Mission {
 struct Addon {
     prefix: String,
     addon_name: String,
+    required_addons: Vec<String>,
     missions: Vec<MissionClass>,
 }
 
@@ -527,40 +1752,612 @@ impl Addon {
     pub fn from_parts(
         prefix: String,
         addon_name: String,
-        missions: Vec<(PathBuf, Mission)>,
+        required_addons: Vec<String>,
+        missions: Vec<MissionClass>,
     ) -> Self {
-        let missions = missions
-            .into_iter()
-            .map(|(directory, mission)| {
-                let directory = format!(
-                    r"{}\{}\missions\{}",
-                    prefix,
-                    addon_name,
-                    directory
-                        .parent()
-                        .map(|p| p.file_name().map(|p| p.to_string_lossy().to_owned()))
-                        .flatten()
-                        .unwrap()
-                );
-                MissionClass {
-                    briefing_name: format!("[{}] {}", prefix, mission.class_name()),
-                    class_name: mission.class_name(),
-                    directory,
-                }
-            })
-            .collect();
-
         Addon {
             prefix,
             addon_name,
+            required_addons,
             missions,
         }
     }
 }
 
+/// A mission's entry in CfgMissions
 #[derive(Serialize)]
 struct MissionClass {
     class_name: String,
     briefing_name: String,
+    /// Mission folder, relative to the PBO prefix
     directory: String,
+    /// Also listed in CfgMissions.Missions
+    singleplayer: bool,
+}
+
+impl MissionClass {
+    fn new(mission: &Mission, pbo_prefix: &str, singleplayer: bool) -> Self {
+        MissionClass {
+            class_name: mission.class_name(),
+            briefing_name: config_string(&mission.briefing_name),
+            directory: format!(r"{}\missions\{}", pbo_prefix, mission.mission_name()),
+            singleplayer,
+        }
+    }
+}
+
+/// Deterministic per-mission `randomSeed`, so rebuilding a mission doesn't change its SQM
+fn random_seed(class_name: &str) -> u32 {
+    // FNV-1a
+    let hash = class_name
+        .bytes()
+        .fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+
+    hash % i32::MAX as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_entry<'a>(class: &'a ConfigClass, path: &[&str]) -> Option<&'a ConfigEntry> {
+        class.get(&path.join("/"))
+    }
+
+    fn find_string<'a>(class: &'a ConfigClass, path: &[&str]) -> Option<&'a str> {
+        match find_entry(class, path)? {
+            ConfigEntry::StringEntry(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn find_int(class: &ConfigClass, path: &[&str]) -> Option<i32> {
+        match find_entry(class, path)? {
+            ConfigEntry::IntEntry(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn float_elements(class: &ConfigClass, path: &[&str]) -> Vec<f32> {
+        match find_entry(class, path) {
+            Some(ConfigEntry::ArrayEntry(array)) => array
+                .elements
+                .iter()
+                .map(|element| match element {
+                    ConfigArrayElement::FloatElement(value) => *value,
+                    ConfigArrayElement::IntElement(value) => *value as f32,
+                    _ => f32::NAN,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} != {:?}", actual, expected);
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_offset_classes_rotation() -> Result<()> {
+        let composition = Config::from_string(
+            r#"
+            class Item0 {
+                dataType = "Group";
+                class Entities {
+                    class Item0 {
+                        dataType = "Object";
+                        class PositionInfo {
+                            position[] = {10, 1, 0};
+                            angles[] = {0, 0, 0};
+                        };
+                    };
+                };
+                class Waypoints {
+                    class Item0 {
+                        position[] = {0, 0, 10};
+                    };
+                };
+            };
+            class Item1 {
+                dataType = "Marker";
+                position[] = {0, 0, 0};
+                angle = 300;
+            };
+            "#
+            .to_string(),
+            None,
+            &Vec::new(),
+        )?;
+
+        let entries = offset_classes(composition.into_inner().entries.unwrap(), (100., 5., 200.), 90.);
+        let class = ConfigClass {
+            parent: String::new(),
+            is_external: false,
+            is_deletion: false,
+            entries: Some(entries),
+        };
+
+        // East of the center turns to south, north turns to east
+        assert_close(&float_elements(&class, &["Item0", "Entities", "Item0", "PositionInfo", "position"]), &[100., 6., 190.]);
+        assert_close(&float_elements(&class, &["Item0", "Waypoints", "Item0", "position"]), &[110., 5., 200.]);
+        assert_close(
+            &float_elements(&class, &["Item0", "Entities", "Item0", "PositionInfo", "angles"]),
+            &[0., std::f32::consts::FRAC_PI_2, 0.],
+        );
+        assert_close(&float_elements(&class, &["Item1", "position"]), &[100., 5., 200.]);
+        assert!(matches!(find_entry(&class, &["Item1", "angle"]), Some(ConfigEntry::FloatEntry(angle)) if (angle - 30.).abs() < 1e-3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placement_resolve() -> Result<()> {
+        let mut terrains = TerrainCatalogue::builtin()?;
+        terrains.extend(toml::from_str(
+            r#"
+            [Altis.spawns]
+            FOB = [1000, 0, 2000]
+
+            [Custom]
+            size = 4096
+            "#,
+        )?)?;
+
+        let placement = PlacementConfig::SpawnRotated(("spawn:fob".to_string(), 90.)).resolve("altis", &terrains)?;
+        assert_eq!(placement.position, (1000., 0., 2000.));
+        assert_eq!(placement.rotation, 90.);
+
+        let placement = PlacementConfig::Spawn("spawn:airfield".to_string()).resolve("Altis", &terrains)?;
+        assert!(terrains.get("Altis").unwrap().contains(placement.position));
+
+        let placement = PlacementConfig::Spawn("center".to_string()).resolve("Custom", &terrains)?;
+        assert_eq!(placement.position, (2048., 0., 2048.));
+
+        assert!(PlacementConfig::Spawn("spawn:nowhere".to_string()).resolve("Altis", &terrains).is_err());
+        assert!(PlacementConfig::Spawn("airfield".to_string()).resolve("Altis", &terrains).is_err());
+        assert!(PlacementConfig::Spawn("center".to_string()).resolve("Unknown", &terrains).is_err());
+
+        let custom = terrains.get("Custom").unwrap();
+        assert!(custom.check_bounds((4096., 10., 0.)).is_ok());
+        assert!(custom.check_bounds((5000., 0., 100.)).is_err());
+        assert!(custom.check_bounds((100., 0., -1.)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_place_composition() -> Result<()> {
+        let mut terrains = TerrainCatalogue::builtin()?;
+        terrains.extend(toml::from_str(
+            r#"
+            [Small]
+            size = 4096
+
+            [Large]
+            size = 20480
+            "#,
+        )?)?;
+
+        let mission_settings: MissionSettings = toml::from_str(
+            r#"
+            maps = [["Small", "center"], ["Large", "center", 90], ["Tiny", [10, 0, 20]], "Altis"]
+            composition = "compositions/base"
+            composition_offset = [100, 0, -100]
+            "#,
+        )?;
+
+        let mut composition = Composition {
+            path: PathBuf::from("compositions/base"),
+            header: Config::from_string("version = 1;".to_string(), None, &Vec::new())?,
+            composition: Config::from_string("center[] = {1500, 0, 1500};".to_string(), None, &Vec::new())?,
+            offset: mission_settings.composition_offset,
+            ignore_center: false,
+            placements: HashMap::new(),
+        };
+
+        let configs = mission_settings.compositions();
+        place_composition(&mut composition, &configs[0], &terrains)?;
+
+        // "center" opts into the middle of the terrain, whatever its size
+        assert_eq!(composition.get_placement("Small")?, (2048., 0., 2048.));
+        assert_eq!(composition.get_placement("large")?, (10240., 0., 10240.));
+        assert_eq!(composition.get_rotation("Large"), 90.);
+        assert_eq!(composition.get_placement("Tiny")?, (10., 0., 20.));
+        // Maps without a placement keep the composition's own center and offset
+        assert_eq!(composition.get_placement("Altis")?, (1600., 0., 1400.));
+
+        check_placements(&mission_settings, &terrains, &[composition])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_config_rapified() -> Result<()> {
+        let text = "version = 54;\nclass Mission {\n    class Intel {\n        briefingName = \"Test\";\n    };\n};\n";
+
+        let config = read_config(&mut std::io::Cursor::new(text.as_bytes()))?;
+        assert_eq!(find_string(config.inner(), &["Mission", "Intel", "briefingName"]), Some("Test"));
+
+        let mut rapified = Vec::new();
+        config.write_rapified(&mut rapified)?;
+        assert!(rapified.starts_with(RAPIFIED_MAGIC));
+
+        let config = read_config(&mut std::io::Cursor::new(rapified))?;
+        assert_eq!(find_string(config.inner(), &["Mission", "Intel", "briefingName"]), Some("Test"));
+        assert_eq!(find_int(config.inner(), &["version"]), Some(54));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scenario_settings() -> Result<()> {
+        let mission_settings: MissionSettings = toml::from_str(
+            r#"
+            maps = ["Altis"]
+            game_type = "Zeus"
+            respawn_delay = 5
+
+            [settings]
+            respawn = "side"
+            respawn_templates = ["MenuPosition", "Tickets"]
+            disabled_ai = false
+            max_players = 64
+
+            [[settings.params]]
+            name = "Daytime"
+            title = "Time of \"day\""
+            values = [6, 12, 18]
+            texts = ["Morning", "Noon", "Evening"]
+            default = 12
+
+            [cba_settings]
+            ace_medical_level = { value = 2, force = true }
+            ace_map_gestures_color = [1, 0.5, 0, 1]
+            acre_sys_core_defaultRadio = "ACRE_PRC343"
+            ace_hearing_enabled = false
+            "#,
+        )?;
+
+        let handlebars = create_handlebars()?;
+        let description = render_description(&handlebars, &mission_settings, mission_settings.settings.as_ref().unwrap())?;
+
+        let config = Config::from_string(description, None, &Vec::new())?;
+        let description = config.inner();
+
+        assert_eq!(find_int(description, &["respawn"]), Some(5));
+        assert_eq!(find_int(description, &["respawnDelay"]), Some(5));
+        assert_eq!(find_int(description, &["disabledAI"]), Some(0));
+        assert_eq!(find_string(description, &["Header", "gameType"]), Some("Zeus"));
+        assert_eq!(find_int(description, &["Header", "minPlayers"]), Some(1));
+        assert_eq!(find_int(description, &["Header", "maxPlayers"]), Some(64));
+        assert_eq!(find_string(description, &["Params", "Daytime", "title"]), Some("Time of \"day\""));
+        assert_eq!(find_int(description, &["Params", "Daytime", "default"]), Some(12));
+
+        let cba_settings = cba_settings_sqf(&mission_settings.cba_settings)?;
+        assert_eq!(
+            cba_settings,
+            [
+                "ace_hearing_enabled = false;",
+                "ace_map_gestures_color = [1, 0.5, 0, 1];",
+                "force ace_medical_level = 2;",
+                "acre_sys_core_defaultRadio = \"ACRE_PRC343\";",
+            ]
+            .join("\n")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mission_sqm_template() -> Result<()> {
+        let template = serde_json::json!({
+            "author": "LAAT",
+            "respawn": 3,
+            "respawn_delay": 5,
+            "respawn_template_count": 2,
+            "respawn_templates": ["MenuPosition", "Tickets"],
+            "disabled_ai": 1,
+            "min_players": 1,
+            "max_players": 64,
+            "source_name": "LAAT_AltisZeusMission",
+            "briefing_name": "Zeus",
+            "game_type": "Zeus",
+            "random_seed": 1234,
+            "camera_x": 0.,
+            "camera_y": 100.,
+            "camera_z": -100.,
+        });
+
+        let sqm = create_handlebars()?.render("mission.sqm", &template)?;
+        let config = Config::from_string(sqm, None, &Vec::new())?;
+
+        let value = ["CustomAttributes", "Category0", "Attribute0", "Value", "data", "value"];
+        assert_eq!(find_int(config.inner(), &[&value[..], &["items"]].concat()), Some(2));
+        assert_eq!(
+            find_string(config.inner(), &[&value[..], &["Item1", "data", "value"]].concat()),
+            Some("Tickets")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_param_validation() {
+        let param = ParamConfig {
+            name: "Daytime".to_string(),
+            title: "Time of day".to_string(),
+            values: vec![6, 12],
+            texts: None,
+            default: 18,
+        };
+
+        assert!(param.texts().is_err());
+
+        let param = ParamConfig {
+            default: 12,
+            texts: Some(vec!["Morning".to_string()]),
+            ..param
+        };
+
+        assert!(param.texts().is_err());
+    }
+
+    #[test]
+    fn test_slots() -> Result<()> {
+        let slots: SlotsConfig = toml::from_str(
+            r#"
+            zeus = 2
+
+            [[groups]]
+            side = "West"
+            name = "Alpha 1-1"
+            roles = [
+                { class = "B_Soldier_SL_F", description = "Squad Leader", name = "alpha_lead" },
+                { class = "B_Soldier_F", description = "Rifleman", count = 3 },
+            ]
+            "#,
+        )?;
+
+        let items = slots.to_items(&create_handlebars()?, (100., 0., 200.))?;
+
+        let mut sqm = ConfigClass::new();
+        merge_entities(&mut sqm, items, Vec::new())?;
+
+        let entities = ["Mission", "Entities"];
+        let path = |rest: &[&'static str]| entities.iter().chain(rest).copied().collect::<Vec<_>>();
+
+        // Zeus group, playable group and one module per Zeus slot
+        assert_eq!(find_int(&sqm, &path(&["items"])), Some(4));
+        assert_eq!(find_string(&sqm, &path(&["Item0", "side"])), Some("Logic"));
+        assert_eq!(find_string(&sqm, &path(&["Item0", "Entities", "Item1", "Attributes", "name"])), Some("zeus_2"));
+
+        assert_eq!(find_int(&sqm, &path(&["Item1", "Entities", "items"])), Some(4));
+        assert_eq!(find_string(&sqm, &path(&["Item1", "Entities", "Item0", "type"])), Some("B_Soldier_SL_F"));
+        assert_eq!(find_string(&sqm, &path(&["Item1", "Entities", "Item0", "Attributes", "name"])), Some("alpha_lead"));
+        assert_eq!(
+            find_string(&sqm, &path(&["Item1", "Entities", "Item3", "Attributes", "description"])),
+            Some("Rifleman@Alpha 1-1")
+        );
+        assert_eq!(find_int(&sqm, &path(&["Item1", "Entities", "Item3", "Attributes", "isPlayable"])), Some(1));
+
+        assert_eq!(find_string(&sqm, &path(&["Item3", "type"])), Some("ModuleCurator_F"));
+        assert_eq!(
+            find_string(&sqm, &path(&["Item3", "CustomAttributes", "Attribute0", "Value", "data", "value"])),
+            Some("zeus_2")
+        );
+
+        // Groups, units and modules all get unique ids
+        assert_eq!(find_int(&sqm, &["ItemIDProvider", "nextID"]), Some(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_entities() -> Result<()> {
+        let sqm = Config::from_string(
+            r#"
+            version = 54;
+            class Mission {
+                class Intel {};
+                class Entities {
+                    items = 1;
+                    class Item0 { dataType = "Object"; id = 0; };
+                };
+            };
+            "#
+            .to_string(),
+            None,
+            &Vec::new(),
+        )?;
+
+        let composition = Config::from_string(
+            r#"
+            class items {
+                items = 2;
+                class Item0 { dataType = "Object"; id = 0; };
+                class Item1 {
+                    dataType = "Group";
+                    id = 1;
+                    class Entities {
+                        items = 1;
+                        class Item0 { dataType = "Object"; id = 2; };
+                    };
+                };
+            };
+            "#
+            .to_string(),
+            None,
+            &Vec::new(),
+        )?;
+
+        let items = composition.class("items").expect("Missing items").entries().to_vec();
+
+        let mut sqm = sqm.into_inner();
+        merge_entities(&mut sqm, items, Vec::new())?;
+
+        assert_eq!(find_int(&sqm, &["Mission", "Entities", "items"]), Some(3));
+        assert_eq!(find_int(&sqm, &["Mission", "Entities", "Item0", "id"]), Some(0));
+        assert_eq!(find_int(&sqm, &["Mission", "Entities", "Item1", "id"]), Some(1));
+        assert_eq!(find_int(&sqm, &["Mission", "Entities", "Item2", "id"]), Some(2));
+        assert_eq!(find_int(&sqm, &["Mission", "Entities", "Item2", "Entities", "Item0", "id"]), Some(3));
+        assert_eq!(find_int(&sqm, &["ItemIDProvider", "nextID"]), Some(4));
+
+        // Entry order is preserved
+        let names: Vec<&str> = sqm.class("Mission").unwrap().entries().iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["Intel", "Entities"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_links() -> Result<()> {
+        let sqm = Config::from_string(
+            r#"
+            version = 54;
+            class Mission {
+                class Entities {
+                    items = 2;
+                    class Item0 { dataType = "Object"; id = 0; };
+                    class Item1 { dataType = "Logic"; id = 1; };
+                };
+                class Connections {
+                    class LinkIDProvider { nextID = 1; };
+                    class Links {
+                        items = 1;
+                        class Item0 {
+                            linkID = 0;
+                            item0 = 1;
+                            item1 = 0;
+                            class CustomData { type = "Sync"; };
+                        };
+                    };
+                };
+            };
+            "#
+            .to_string(),
+            None,
+            &Vec::new(),
+        )?;
+
+        let composition = Config::from_string(
+            r#"
+            version = 54;
+            center[] = {1000, 0, 1000};
+            class items {
+                items = 2;
+                class Item0 {
+                    dataType = "Object";
+                    id = 0;
+                    class CrewLinks {
+                        class LinkIDProvider { nextID = 1; };
+                        class Links {
+                            items = 1;
+                            class Item0 { linkID = 0; item0 = 1; item1 = 0; };
+                        };
+                    };
+                };
+                class Item1 { dataType = "Object"; id = 1; };
+            };
+            class connections {
+                class LinkIDProvider { nextID = 1; };
+                class Links {
+                    items = 1;
+                    class Item0 {
+                        linkID = 0;
+                        item0 = 0;
+                        item1 = 1;
+                        class CustomData { type = "Sync"; };
+                    };
+                };
+            };
+            "#
+            .to_string(),
+            None,
+            &Vec::new(),
+        )?;
+
+        let composition = Composition {
+            path: PathBuf::from("compositions/base"),
+            header: Config::from_string("version = 1;".to_string(), None, &Vec::new())?,
+            composition,
+            offset: (0., 0., 0.),
+            ignore_center: true,
+            placements: HashMap::new(),
+        };
+
+        let mut sqm = sqm.into_inner();
+        merge_entities(&mut sqm, composition.get_offseted_items("Altis")?, composition.get_links())?;
+
+        // Crew links inside entities follow the renumbered ids
+        let crew = ["Mission", "Entities", "Item2", "CrewLinks", "Links", "Item0"];
+        assert_eq!(find_int(&sqm, &[&crew[..], &["item0"]].concat()), Some(3));
+        assert_eq!(find_int(&sqm, &[&crew[..], &["item1"]].concat()), Some(2));
+
+        // Sync links are appended to the mission's
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "Links", "items"]), Some(2));
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "Links", "Item0", "item0"]), Some(1));
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "Links", "Item1", "linkID"]), Some(1));
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "Links", "Item1", "item0"]), Some(2));
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "Links", "Item1", "item1"]), Some(3));
+        assert_eq!(
+            find_string(&sqm, &["Mission", "Connections", "Links", "Item1", "CustomData", "type"]),
+            Some("Sync")
+        );
+        assert_eq!(find_int(&sqm, &["Mission", "Connections", "LinkIDProvider", "nextID"]), Some(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cfg_missions() -> Result<()> {
+        let missions = vec![
+            MissionClass {
+                class_name: "LAAT_AltisZeusMission".to_string(),
+                briefing_name: config_string(r#"[LAAT] "Zeus" (Altis)"#),
+                directory: r"LAAT\Missions\missions\LAAT_AltisZeusMission.Altis".to_string(),
+                singleplayer: false,
+            },
+            MissionClass {
+                class_name: "LAAT_TanoaZeusMission".to_string(),
+                briefing_name: config_string("[LAAT] Zeus (Tanoa)"),
+                directory: r"LAAT\Missions\missions\LAAT_TanoaZeusMission.Tanoa".to_string(),
+                singleplayer: true,
+            },
+        ];
+
+        let addon = Addon::from_parts(
+            "LAAT".to_string(),
+            "Missions".to_string(),
+            vec!["cba_main".to_string()],
+            missions,
+        );
+
+        let config_cpp = create_handlebars()?.render("missions_addon", &addon)?;
+        let config = Config::from_string(config_cpp, None, &Vec::new())?;
+        let root = config.inner();
+
+        assert_eq!(
+            find_string(root, &["CfgMissions", "MPMissions", "LAAT_AltisZeusMission", "directory"]),
+            Some(r"LAAT\Missions\missions\LAAT_AltisZeusMission.Altis")
+        );
+        assert_eq!(
+            find_string(root, &["CfgMissions", "MPMissions", "LAAT_AltisZeusMission", "briefingName"]),
+            Some(r#"[LAAT] "Zeus" (Altis)"#)
+        );
+        assert!(find_entry(root, &["CfgMissions", "Missions", "LAAT_AltisZeusMission"]).is_none());
+        assert_eq!(
+            find_string(root, &["CfgMissions", "Missions", "LAAT_TanoaZeusMission", "briefingName"]),
+            Some("[LAAT] Zeus (Tanoa)")
+        );
+
+        match find_entry(root, &["CfgPatches", "LAAT_Missions", "requiredAddons"]) {
+            Some(ConfigEntry::ArrayEntry(array)) => assert_eq!(array.elements.len(), 1),
+            _ => panic!("Missing requiredAddons[]"),
+        }
+
+        Ok(())
+    }
 }
@@ -1,16 +1,27 @@
 //! Compiler Plugin for generating a Music addon from a folder of music.
 //!
-//! This plugin only works with .ogg files, and by-default will look in ./assets/music and any
-//! subfolders for .ogg files
+//! This plugin looks in ./assets/music and any subfolders for .ogg files. WAV, MP3 and FLAC files
+//! are transcoded to Ogg Vorbis, at the bitrate set in the `[music]` section of LAAT.toml.
+//!
+//! Track names come from their title and artist tags, or file names, and can be overridden in a
+//! `music.toml` sidecar in the music folder, along with each track's volume, music class and theme.
+//! Tracks can also be normalized to the same loudness.
 //!
 //! If music is inside a subfolder, it will be catagorized by that subfolders name.
 //!
 //! This music will show up in the Zeus "Play Music" module, and will be prefixed by the prefix
 //! defined in LAAT.toml
 
+use super::util::{config_string, folder_name, format_db};
+use crate::audio;
 use crate::context::AddonManager;
+use crate::identifier;
 use crate::Plugin;
 use ogg_metadata::{read_format, OggFormat, AudioMetadata};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{RootSchema, Schema};
+use schemars::JsonSchema;
+use std::collections::HashMap;
 use std::path::Path;
 
 use walkdir::DirEntry;
@@ -18,10 +29,62 @@ use std::path::PathBuf;
 use crate::{Result, context::BuildContext, create_handlebars};
 
 use serde::{Serialize, Deserialize};
+use futures_util::future::join_all;
 
 
 const MUSIC_PATH: &str = r"data\Music";
 const ADDON_NAME: &str = "Music";
+const MUSIC_SETTINGS_KEY: &str = "music";
+const SIDECAR_FILE: &str = "music.toml";
+
+/// `[music]` section of LAAT.toml
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct MusicSettings {
+    /// Average bitrate, in kbps, of tracks transcoded to Ogg Vorbis
+    #[serde(default = "default_bitrate")]
+    bitrate: u32,
+
+    /// Measure each track's loudness, and set its volume to reach `loudness_target`
+    #[serde(default)]
+    normalize: bool,
+
+    /// Integrated loudness (LUFS) that tracks are normalized to
+    #[serde(default = "default_loudness_target")]
+    loudness_target: f32,
+
+    /// Re-encode tracks with normalization applied to the audio, rather than setting each
+    /// track's volume in CfgMusic. Gain that would clip the audio is still set in CfgMusic.
+    #[serde(default)]
+    bake_normalization: bool,
+}
+
+fn default_bitrate() -> u32 {
+    160
+}
+
+fn default_loudness_target() -> f32 {
+    -16.
+}
+
+/// Per-track overrides in `music.toml`, keyed by the track's path in the music folder, e.g.
+/// "80s/Take_On_Me.ogg"
+type SidecarFile = HashMap<String, TrackConfig>;
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+struct TrackConfig {
+    /// Name shown in the Zeus music list
+    name: Option<String>,
+
+    /// Volume adjustment in decibels, on top of any normalization
+    #[serde(default)]
+    volume: f32,
+
+    /// Music class (category) to list the track under, instead of its folder
+    class: Option<String>,
+
+    /// CfgMusic theme, e.g. "safe", "combat" or "stealth"
+    theme: Option<String>,
+}
 
 pub struct MusicPlugin;
 
@@ -34,6 +97,14 @@ impl Plugin for MusicPlugin {
     fn name(&self) -> String {
         "music".to_string()
     }
+
+    fn config_schema(&self, generator: &mut SchemaGenerator) -> Option<Schema> {
+        Some(generator.subschema_for::<MusicSettings>())
+    }
+
+    fn file_schemas(&self) -> Vec<(String, RootSchema)> {
+        vec![(ADDON_NAME.to_lowercase(), SchemaGenerator::default().into_root_schema_for::<SidecarFile>())]
+    }
 }
 
 
@@ -42,58 +113,119 @@ pub async fn build_music_addon(
     build_context: BuildContext,
 ) -> Result<()> {
     let BuildContext {
-        assets_path,
         prefix,
         ..
     } = build_context.clone();
 
-    let mut manager = AddonManager::from_context(ADDON_NAME.to_string(), build_context);
+    let settings: MusicSettings = build_context.plugin_config(MUSIC_SETTINGS_KEY)?;
 
-    // Walkdir through ./assets/music
-    let dir = walkdir::WalkDir::new(format!("{}/music", assets_path));
+    if settings.bake_normalization && !audio::CAN_ENCODE {
+        warn!("bake_normalization needs LAAT built with the `transcode` feature, setting volumes in CfgMusic instead");
+    }
+
+    let asset_paths = build_context.asset_paths();
+    let required_addons = build_context.required_addons();
+
+    let mut manager = AddonManager::from_context(ADDON_NAME.to_string(), build_context);
 
     let mut music_classes = Vec::new();
     let mut music_files = Vec::new();
 
-    for entry in dir {
-        match entry {
-            Ok(entry) => {
-                trace!("Walking entry: {}", entry.path().display());
-
-                let file_type = entry.file_type();
-                let file_name = entry.file_name().to_owned().to_string_lossy().to_string();
-
-                // For each subfolder - create a CfgMusicClasses entry
-                if file_type.is_dir() {
-                    music_classes.push((file_name, entry.path().display().to_string()));
-                // For each ogg file - create a CfgMusic entry which
-                // references the CfgMusicClasses for it's folder.
-                // Also - calculate the length (in seconds) of the ogg file, and add that into the template
-                } else if file_type.is_file() && file_name.contains(".ogg") {
-                    if let Ok(entry) = Track::from_dir_entry(entry, &prefix, &ADDON_NAME) {
-                        music_files.push(entry);
+    // Walkdir through ./assets/music, and any shared asset folders
+    for assets_path in asset_paths.iter() {
+        let music_path = PathBuf::from(format!("{}/music", assets_path));
+        let sidecar = load_sidecar(&music_path)?;
+
+        for entry in walkdir::WalkDir::new(&music_path) {
+            match entry {
+                Ok(entry) => {
+                    trace!("Walking entry: {}", entry.path().display());
+
+                    let file_type = entry.file_type();
+                    let file_name = entry.file_name().to_owned().to_string_lossy().to_string();
+
+                    // For each subfolder - create a CfgMusicClasses entry
+                    if file_type.is_dir() {
+                        add_music_class(&mut music_classes, &prefix, &file_name, entry.path())?;
+                    // For each audio file - create a CfgMusic entry which
+                    // references the CfgMusicClasses for it's folder.
+                    // Also - calculate the length (in seconds) of the file, and add that into the template
+                    } else if file_type.is_file() && entry.depth() == 1 && file_name == SIDECAR_FILE {
+                        continue;
+                    } else if file_type.is_file() {
+                        let path = entry.path().display().to_string();
+                        let key = sidecar_key(&music_path, entry.path());
+                        let config = sidecar.get(&key).cloned().unwrap_or_default();
+
+                        match Track::from_dir_entry(entry, &music_path, &prefix, ADDON_NAME, config) {
+                            Ok(track) => music_files.push(track),
+                            Err(why) => warn!("Skipping {}: {}", path, why),
+                        }
                     }
                 }
+                Err(why) => warn!("Error walking entry: {:?}", why),
             }
-            Err(why) => warn!("Error walking entry: {:?}", why),
         }
     }
 
-    debug!("Files: {:?}", music_files);
+    check_collisions(&music_files)?;
+
+    // Transcode, measure and normalize the tracks that need it
+    let processed = join_all(music_files.iter().map(|track| {
+        let track = track.clone();
+        let settings = settings.clone();
+
+        tokio::task::spawn_blocking(move || track.process(&settings).map_err(|why| why.to_string()))
+    }))
+    .await;
+
+    let mut tracks = Vec::new();
+
+    for (mut track, processed) in music_files.into_iter().zip(processed) {
+        let folder = Path::new("data/Music").join(&track.folder);
+
+        let processed = match processed {
+            Ok(Ok(processed)) => processed,
+            Ok(Err(why)) => {
+                warn!("Skipping {}: {}", track.path.display(), why);
+                continue;
+            }
+            Err(why) => {
+                warn!("Skipping {}: {}", track.path.display(), why);
+                continue;
+            }
+        };
+
+        track.db = format_db(processed.gain + track.volume);
+
+        match processed.ogg {
+            Some(ogg) => manager.add_binary_file(ogg, folder.join(&track.file_name)),
+            None => {
+                manager.add_asset(track.path.clone(), Some(folder))?;
+            }
+        }
+
+        add_music_class(&mut music_classes, &prefix, &track.class, &track.path)?;
+
+        tracks.push(track);
+    }
+
+    debug!("Files: {:?}", tracks);
     debug!("Classes: {:?}", music_classes);
 
     // Template a {prefix}_Music addon
     let music_addon = MusicAddon {
         addon_name: ADDON_NAME.to_string(),
-        track_list: music_files.iter().map(|file| format!("\"{}\"", file.class_name)).collect::<Vec<_>>().join(", "),
-        tracks: music_files.clone(),
-        classes: music_classes.into_iter().map(|(class, _path)| {
+        track_list: tracks.iter().map(|file| format!("\"{}\"", file.class_name)).collect::<Vec<_>>().join(", "),
+        tracks,
+        classes: music_classes.into_iter().map(|(class, _)| {
             MusicClass {
-                class_name: format!("{}{}", prefix, class),
-                display_name: format!("[{}] {}", prefix, class),
+                class_name: music_class_name(&prefix, &class),
+                display_name: config_string(&format!("[{}] {}", prefix, class)),
             }
         }).collect(),
         prefix: prefix.clone(),
+        required_addons,
     };
 
 
@@ -103,21 +235,92 @@ pub async fn build_music_addon(
 
     manager.add_file(config_cpp, "config.cpp".into());
 
-    // Copy the music files over
-    for track in music_files {
-        let new_path = manager.add_asset(track.path, Some("data/Music".into()))?;
-    }
-
     manager.build_addon().await?;
 
     Ok(())
 }
 
+/// Fail if two tracks would have the same CfgMusic class, which Arma treats case-insensitively
+fn check_collisions(tracks: &[Track]) -> Result<()> {
+    let mut class_names: HashMap<String, &Path> = HashMap::new();
+
+    for track in tracks {
+        if let Some(other) = class_names.insert(track.class_name.to_lowercase(), &track.path) {
+            return Err(format!(
+                "Music tracks {} and {} both have the class name {}, rename one of them",
+                other.display(),
+                track.path.display(),
+                track.class_name
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Add a CfgMusicClasses entry, unless it's already there. Fail if a different class would have
+/// the same class name once sanitized, which Arma treats case-insensitively.
+fn add_music_class(classes: &mut Vec<(String, PathBuf)>, prefix: &str, class: &str, source: &Path) -> Result<()> {
+    let class_name = music_class_name(prefix, class);
+
+    match classes
+        .iter()
+        .find(|(other, _)| music_class_name(prefix, other).eq_ignore_ascii_case(&class_name))
+    {
+        Some((other, _)) if other == class => Ok(()),
+        Some((_, other_source)) => Err(format!(
+            "Music classes from {} and {} both have the class name {}, rename one of them",
+            other_source.display(),
+            source.display(),
+            class_name
+        )
+        .into()),
+        None => {
+            classes.push((class.to_string(), source.to_path_buf()));
+            Ok(())
+        }
+    }
+}
+
+fn music_class_name(prefix: &str, class: &str) -> String {
+    identifier::sanitize(&format!("{}{}", prefix, class))
+}
+
+/// Load `music.toml` from a music folder, if there is one
+fn load_sidecar(music_path: &Path) -> Result<SidecarFile> {
+    let path = music_path.join(SIDECAR_FILE);
+
+    if !path.exists() {
+        return Ok(SidecarFile::new());
+    }
+
+    let contents = std::fs::read_to_string(&path)?;
+    let sidecar: SidecarFile =
+        toml::from_str(&contents).map_err(|why| format!("Invalid {}: {}", path.display(), why))?;
+
+    // Keys are matched regardless of path separator and case
+    Ok(sidecar
+        .into_iter()
+        .map(|(key, config)| (key.replace('\\', "/").to_lowercase(), config))
+        .collect())
+}
+
+fn sidecar_key(music_path: &Path, path: &Path) -> String {
+    path.strip_prefix(music_path)
+        .unwrap_or(path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_lowercase()
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct MusicAddon {
     prefix: String,
     addon_name: String,
+    required_addons: Vec<String>,
     track_list: String,
     tracks: Vec<Track>,
     classes: Vec<MusicClass>,
@@ -136,41 +339,160 @@ struct Track {
     file_name: String,
     pretty_name: String,
     duration: usize,
+    /// Music class, without the prefix
+    class: String,
+    /// Folder of the track in the music folder
+    folder: PathBuf,
     music_class: String,
+    theme: Option<String>,
     path: PathBuf,
-    sound_path: String
+    sound_path: String,
+    /// Volume in CfgMusic `sound[]`
+    db: String,
+    /// Volume adjustment from music.toml
+    volume: f32,
+    /// Whether the file needs transcoding to Ogg Vorbis
+    transcode: bool,
+}
+
+/// Result of transcoding and normalizing a track
+struct ProcessedTrack {
+    /// Re-encoded audio, if the file can't be copied as is
+    ogg: Option<Vec<u8>>,
+    /// Normalization gain left to set in CfgMusic, in decibels
+    gain: f32,
 }
 
 impl Track {
-    pub fn from_dir_entry(entry: DirEntry, prefix: &str, addon_name: &str) -> Result<Self> {
-        let file_name = entry.file_name().to_owned().to_string_lossy().to_string();
+    pub fn from_dir_entry(entry: DirEntry, music_path: &Path, prefix: &str, addon_name: &str, config: TrackConfig) -> Result<Self> {
+        let path = entry.path();
+
+        let folder = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(music_path).ok())
+            .map(Path::to_owned)
+            .unwrap_or_default();
+
+        let folder_name = folder_name(music_path, &folder);
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let transcode = if extension == "ogg" {
+            false
+        } else if audio::TRANSCODED_EXTENSIONS.contains(&extension.as_str()) {
+            true
+        } else {
+            return Err(format!("Unsupported format, expected .ogg or one of: .{}", audio::TRANSCODED_EXTENSIONS.join(", .")).into());
+        };
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or("Missing file name")?;
 
-        let name = file_name.clone().split(".ogg").next().unwrap_or(&file_name).to_string();
+        let duration = if transcode {
+            audio::duration(path)?
+        } else {
+            Self::get_duration_from_path(path)?
+        };
 
-        let class = entry
-                .path()
-                .parent()
-                .unwrap()
-                .file_name()
-                .unwrap()
-                .to_owned()
-                .to_string_lossy()
-                .to_string();
+        // music.toml, then the title and artist tags, then the file name
+        let pretty_name = match config.name {
+            Some(name) => name,
+            None => match audio::tags(path) {
+                Ok(tags) => tags.display_name(),
+                Err(why) => {
+                    debug!("Failed to read tags from {}: {}", path.display(), why);
+                    None
+                }
+            }
+            .unwrap_or_else(|| name.replace("_", " ")),
+        };
+
+        let class = config.class.unwrap_or_else(|| {
+            path.parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        // Transcoded tracks are written as Ogg Vorbis
+        let file_name = if transcode {
+            format!("{}.ogg", name)
+        } else {
+            entry.file_name().to_string_lossy().to_string()
+        };
 
+        let sound_folder = folder
+            .components()
+            .map(|component| format!(r"{}\", component.as_os_str().to_string_lossy()))
+            .collect::<String>();
 
         let music_file = Track {
-            class_name: name.clone().replace("-", "_").replace(" ", "_"),
-            pretty_name: name.replace("_", " "),
-            duration: Self::get_duration_from_path(entry.path())?,
-            path: entry.path().to_owned(),
-            music_class: format!("{}{}", prefix, class),
-            sound_path: format!(r"{}\{}\{}\{}", prefix, addon_name, MUSIC_PATH, file_name.clone()),
+            class_name: identifier::sanitize(&format!("{}_{}_{}", prefix, folder_name, name)),
+            pretty_name: config_string(&pretty_name),
+            duration,
+            music_class: music_class_name(prefix, &class),
+            class,
+            folder,
+            theme: config.theme.as_deref().map(config_string),
+            path: path.to_owned(),
+            sound_path: format!(r"{}\{}\{}\{}{}", prefix, addon_name, MUSIC_PATH, sound_folder, file_name.clone()),
             file_name,
+            db: format_db(config.volume),
+            volume: config.volume,
+            transcode,
         };
 
         Ok(music_file)
     }
 
+    /// Transcode the track if needed, and measure its loudness to normalize it
+    #[instrument(err, skip(self, settings), fields(path = %self.path.display()))]
+    fn process(&self, settings: &MusicSettings) -> Result<ProcessedTrack> {
+        if !self.transcode && !settings.normalize {
+            return Ok(ProcessedTrack { ogg: None, gain: 0. });
+        }
+
+        // Fail before decoding when the track can't be encoded anyway
+        if self.transcode {
+            audio::check_encoder()?;
+        }
+
+        let mut audio = audio::decode(&self.path)?;
+        let mut gain = 0.;
+
+        if settings.normalize {
+            match audio.loudness() {
+                Some(loudness) => {
+                    gain = settings.loudness_target - loudness;
+                    debug!("{} is {:.1} LUFS, adjusting by {:.1} dB", self.path.display(), loudness, gain);
+                }
+                None => warn!("{} is silent, not normalizing it", self.path.display()),
+            }
+        }
+
+        if settings.bake_normalization && audio::CAN_ENCODE && gain != 0. {
+            // Only bake as much gain as fits without clipping
+            let headroom = -20. * audio.peak().max(f32::EPSILON).log10();
+            let baked = gain.min(headroom);
+
+            info!("Normalizing {}", self.path.display());
+            audio.apply_gain(baked);
+            gain -= baked;
+        } else if !self.transcode {
+            return Ok(ProcessedTrack { ogg: None, gain });
+        }
+
+        info!("Encoding {}", self.path.display());
+        let ogg = audio::encode_vorbis(&audio, settings.bitrate)?;
+
+        Ok(ProcessedTrack { ogg: Some(ogg), gain })
+    }
+
     #[instrument(err)]
     fn get_duration_from_path(path: &Path) -> Result<usize> {
         let file = std::fs::File::open(path)?;
@@ -198,3 +520,26 @@ impl Track {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_music_class() {
+        let mut classes = Vec::new();
+
+        add_music_class(&mut classes, "17th", "Ambient", Path::new("a/music/Ambient")).unwrap();
+        add_music_class(&mut classes, "17th", "Ambient", Path::new("b/music/Ambient")).unwrap();
+        add_music_class(&mut classes, "17th", "Combat-Music", Path::new("a/music/Combat-Music")).unwrap();
+        assert_eq!(classes.len(), 2);
+
+        let why = add_music_class(&mut classes, "17th", "Combat Music", Path::new("a/music/Combat Music"))
+            .unwrap_err()
+            .to_string();
+        assert!(why.contains("a/music/Combat-Music") && why.contains("a/music/Combat Music"), "{}", why);
+
+        assert!(add_music_class(&mut classes, "17th", "ambient", Path::new("b/music/ambient")).is_err());
+        assert_eq!(classes.len(), 2);
+    }
+}
@@ -0,0 +1,567 @@
+//! Compiler Plugin for generating a Sounds addon from folders of sound effects and radio chatter.
+//!
+//! Each folder maps to a config class:
+//!
+//! - ./assets/sounds becomes `CfgSounds`, for `playSound` and `say3D`
+//! - ./assets/radio becomes `CfgRadio`, for `sideRadio` and friends
+//! - ./assets/sfx becomes `CfgSFX`, for looping ambient sound sources
+//!
+//! Like the music plugin, .ogg files are copied as is, and WAV, MP3 and FLAC files are transcoded
+//! to Ogg Vorbis. A text file next to a sound, with the same name, holds its subtitle.
+
+use super::util::{config_string, folder_name, format_db};
+use crate::audio;
+use crate::context::AddonManager;
+use crate::identifier;
+use crate::Plugin;
+use crate::{context::BuildContext, create_handlebars, Result};
+use futures_util::future::join_all;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::DirEntry;
+
+const ADDON_NAME: &str = "Sounds";
+const SOUNDS_SETTINGS_KEY: &str = "sounds";
+const SUBTITLE_EXTENSION: &str = "txt";
+
+/// `[sounds]` section of LAAT.toml
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+struct SoundsSettings {
+    /// Average bitrate, in kbps, of sounds transcoded to Ogg Vorbis
+    #[serde(default = "default_bitrate")]
+    bitrate: u32,
+
+    /// Volume adjustment in decibels for every sound
+    #[serde(default)]
+    volume: f32,
+
+    /// Distance in meters that `CfgSounds` and `CfgSFX` sounds can be heard from
+    #[serde(default = "default_distance")]
+    distance: f32,
+}
+
+fn default_bitrate() -> u32 {
+    160
+}
+
+fn default_distance() -> f32 {
+    100.
+}
+
+/// Config class a folder of sounds is generated into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SoundKind {
+    Sound,
+    Radio,
+    Sfx,
+}
+
+impl SoundKind {
+    const ALL: [SoundKind; 3] = [SoundKind::Sound, SoundKind::Radio, SoundKind::Sfx];
+
+    /// Folder in ./assets, and in the addon's data folder
+    fn folder(self) -> &'static str {
+        match self {
+            SoundKind::Sound => "sounds",
+            SoundKind::Radio => "radio",
+            SoundKind::Sfx => "sfx",
+        }
+    }
+
+    fn config_class(self) -> &'static str {
+        match self {
+            SoundKind::Sound => "CfgSounds",
+            SoundKind::Radio => "CfgRadio",
+            SoundKind::Sfx => "CfgSFX",
+        }
+    }
+}
+
+pub struct SoundsPlugin;
+
+#[async_trait]
+impl Plugin for SoundsPlugin {
+    async fn build(&self, build_context: BuildContext) -> Result<()> {
+        build_sounds_addon(build_context).await
+    }
+
+    fn name(&self) -> String {
+        "sounds".to_string()
+    }
+
+    fn config_schema(&self, generator: &mut SchemaGenerator) -> Option<Schema> {
+        Some(generator.subschema_for::<SoundsSettings>())
+    }
+}
+
+#[instrument(err, skip(build_context))]
+pub async fn build_sounds_addon(build_context: BuildContext) -> Result<()> {
+    let BuildContext { prefix, .. } = build_context.clone();
+
+    let settings: SoundsSettings = build_context.plugin_config(SOUNDS_SETTINGS_KEY)?;
+    let asset_paths = build_context.asset_paths();
+    let required_addons = build_context.required_addons();
+
+    let mut manager = AddonManager::from_context(ADDON_NAME.to_string(), build_context);
+
+    let mut sound_files = Vec::new();
+
+    // Walkdir through ./assets/{sounds,radio,sfx}, and any shared asset folders
+    for kind in SoundKind::ALL.iter().copied() {
+        for assets_path in asset_paths.iter() {
+            let sounds_path = Path::new(assets_path).join(kind.folder());
+
+            if !sounds_path.is_dir() {
+                continue;
+            }
+
+            for entry in walkdir::WalkDir::new(&sounds_path) {
+                match entry {
+                    Ok(entry) => {
+                        trace!("Walking entry: {}", entry.path().display());
+
+                        if !entry.file_type().is_file() || is_subtitle(entry.path()) {
+                            continue;
+                        }
+
+                        let path = entry.path().display().to_string();
+
+                        match Sound::from_dir_entry(entry, kind, &sounds_path, &prefix, &settings) {
+                            Ok(sound) => sound_files.push(sound),
+                            Err(why) => warn!("Skipping {}: {}", path, why),
+                        }
+                    }
+                    Err(why) => warn!("Error walking entry: {:?}", why),
+                }
+            }
+        }
+    }
+
+    check_collisions(&sound_files)?;
+
+    // Transcode the sounds that need it
+    let transcoded = join_all(sound_files.iter().map(|sound| {
+        let path = sound.path.clone();
+        let transcode = sound.transcode;
+        let bitrate = settings.bitrate;
+
+        tokio::task::spawn_blocking(move || {
+            if transcode {
+                info!("Encoding {}", path.display());
+                audio::transcode(&path, bitrate).map(Some).map_err(|why| why.to_string())
+            } else {
+                Ok(None)
+            }
+        })
+    }))
+    .await;
+
+    let mut sounds_addon = SoundsAddon {
+        prefix: prefix.clone(),
+        addon_name: ADDON_NAME.to_string(),
+        required_addons,
+        distance: settings.distance,
+        sounds: Vec::new(),
+        radio: Vec::new(),
+        sfx: Vec::new(),
+    };
+
+    for (sound, transcoded) in sound_files.into_iter().zip(transcoded) {
+        let folder = Path::new("data").join(sound.kind.folder()).join(&sound.folder);
+
+        match transcoded {
+            Ok(Ok(Some(ogg))) => manager.add_binary_file(ogg, folder.join(&sound.file_name)),
+            Ok(Ok(None)) => {
+                manager.add_asset(sound.path.clone(), Some(folder))?;
+            }
+            Ok(Err(why)) => {
+                warn!("Skipping {}: {}", sound.path.display(), why);
+                continue;
+            }
+            Err(why) => {
+                warn!("Skipping {}: {}", sound.path.display(), why);
+                continue;
+            }
+        }
+
+        match sound.kind {
+            SoundKind::Sound => sounds_addon.sounds.push(sound),
+            SoundKind::Radio => sounds_addon.radio.push(sound),
+            SoundKind::Sfx => sounds_addon.sfx.push(sound),
+        }
+    }
+
+    debug!("Sounds: {:?}", sounds_addon);
+
+    // Template a {prefix}_Sounds addon
+    let handlebars = create_handlebars()?;
+    let config_cpp = handlebars.render("sounds_addon", &sounds_addon)?;
+
+    manager.add_file(config_cpp, "config.cpp".into());
+
+    manager.build_addon().await?;
+
+    Ok(())
+}
+
+/// Fail if two sounds would have the same class in the same config, which Arma treats
+/// case-insensitively
+fn check_collisions(sounds: &[Sound]) -> Result<()> {
+    let mut class_names: HashMap<(&str, String), &Path> = HashMap::new();
+
+    for sound in sounds {
+        let key = (sound.kind.config_class(), sound.class_name.to_lowercase());
+
+        if let Some(other) = class_names.insert(key, &sound.path) {
+            return Err(format!(
+                "Sounds {} and {} both have the {} class name {}, rename one of them",
+                other.display(),
+                sound.path.display(),
+                sound.kind.config_class(),
+                sound.class_name
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+fn is_subtitle(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case(SUBTITLE_EXTENSION))
+        .unwrap_or_default()
+}
+
+/// Subtitle from the text file next to a sound, if there is one. Lines are joined, since config
+/// strings can't span lines.
+fn load_subtitle(path: &Path) -> Result<Option<String>> {
+    let path = path.with_extension(SUBTITLE_EXTENSION);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|why| format!("Failed to read subtitle {}: {}", path.display(), why))?;
+
+    let subtitle = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if subtitle.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(config_string(&subtitle)))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SoundsAddon {
+    prefix: String,
+    addon_name: String,
+    required_addons: Vec<String>,
+    distance: f32,
+    sounds: Vec<Sound>,
+    radio: Vec<Sound>,
+    sfx: Vec<Sound>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Sound {
+    #[serde(skip)]
+    kind: SoundKind,
+    class_name: String,
+    file_name: String,
+    pretty_name: String,
+    duration: usize,
+    /// Folder of the sound in its assets folder
+    folder: PathBuf,
+    path: PathBuf,
+    sound_path: String,
+    /// Volume in `sound[]`
+    db: String,
+    subtitle: Option<String>,
+    /// Whether the file needs transcoding to Ogg Vorbis
+    transcode: bool,
+}
+
+impl Sound {
+    fn from_dir_entry(
+        entry: DirEntry,
+        kind: SoundKind,
+        sounds_path: &Path,
+        prefix: &str,
+        settings: &SoundsSettings,
+    ) -> Result<Self> {
+        let path = entry.path();
+
+        let folder = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(sounds_path).ok())
+            .map(Path::to_owned)
+            .unwrap_or_default();
+
+        let folder_name = folder_name(sounds_path, &folder);
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let transcode = if extension == "ogg" {
+            false
+        } else if audio::TRANSCODED_EXTENSIONS.contains(&extension.as_str()) {
+            true
+        } else {
+            return Err(format!(
+                "Unsupported format, expected .ogg, .{} or a .{} subtitle",
+                audio::TRANSCODED_EXTENSIONS.join(", ."),
+                SUBTITLE_EXTENSION
+            )
+            .into());
+        };
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or("Missing file name")?;
+
+        // Transcoded sounds are written as Ogg Vorbis
+        let file_name = if transcode {
+            format!("{}.ogg", name)
+        } else {
+            entry.file_name().to_string_lossy().to_string()
+        };
+
+        let sound_folder = folder
+            .components()
+            .map(|component| format!(r"{}\", component.as_os_str().to_string_lossy()))
+            .collect::<String>();
+
+        Ok(Sound {
+            kind,
+            class_name: identifier::sanitize(&format!("{}_{}_{}", prefix, folder_name, name)),
+            pretty_name: config_string(&name.replace("_", " ")),
+            duration: audio::duration(path)?,
+            folder,
+            path: path.to_owned(),
+            sound_path: format!(
+                r"{}\{}\data\{}\{}{}",
+                prefix,
+                ADDON_NAME,
+                kind.folder(),
+                sound_folder,
+                file_name
+            ),
+            file_name,
+            db: format_db(settings.volume),
+            subtitle: load_subtitle(path)?,
+            transcode,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use armake2::config::{Config, ConfigArrayElement, ConfigEntry};
+
+    fn settings() -> SoundsSettings {
+        SoundsSettings {
+            bitrate: default_bitrate(),
+            volume: -2.,
+            distance: default_distance(),
+        }
+    }
+
+    fn sound(kind: SoundKind, class_name: &str) -> Sound {
+        Sound {
+            kind,
+            class_name: class_name.to_string(),
+            file_name: format!("{}.ogg", class_name),
+            pretty_name: class_name.to_string(),
+            duration: 2,
+            folder: PathBuf::new(),
+            path: PathBuf::from(kind.folder()).join(format!("{}.ogg", class_name)),
+            sound_path: format!(r"LAAT\Sounds\data\{}\{}.ogg", kind.folder(), class_name),
+            db: format_db(0.),
+            subtitle: None,
+            transcode: false,
+        }
+    }
+
+    /// One second of silence, as 8kHz 16-bit mono WAV
+    fn write_wav(path: &Path) {
+        let data_size: u32 = 8000 * 2;
+        let mut wav = Vec::new();
+
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        wav.resize(wav.len() + data_size as usize, 0);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, wav).unwrap();
+    }
+
+    fn load_sounds(sounds_path: &Path, kind: SoundKind) -> Result<Vec<Sound>> {
+        walkdir::WalkDir::new(sounds_path)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| entry.file_type().is_file() && !is_subtitle(entry.path()))
+            .map(|entry| Sound::from_dir_entry(entry, kind, sounds_path, "LAAT", &settings()))
+            .collect()
+    }
+
+    fn class_names(config: &Config, class: &str) -> Vec<String> {
+        match config.inner().get(class) {
+            Some(ConfigEntry::ClassEntry(class)) => class
+                .entries()
+                .iter()
+                .filter(|(_, entry)| matches!(entry, ConfigEntry::ClassEntry(_)))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_sound_kinds() {
+        assert_eq!(SoundKind::Sound.folder(), "sounds");
+        assert_eq!(SoundKind::Sound.config_class(), "CfgSounds");
+        assert_eq!(SoundKind::Radio.folder(), "radio");
+        assert_eq!(SoundKind::Radio.config_class(), "CfgRadio");
+        assert_eq!(SoundKind::Sfx.folder(), "sfx");
+        assert_eq!(SoundKind::Sfx.config_class(), "CfgSFX");
+    }
+
+    #[test]
+    fn test_load_sounds() -> Result<()> {
+        let assets = std::env::temp_dir().join(format!("laat-sounds-{}", std::process::id()));
+        let sounds_path = assets.join("sounds");
+
+        write_wav(&sounds_path.join("Ambient").join("Wind Gust.wav"));
+        std::fs::write(
+            sounds_path.join("Ambient").join("Wind Gust.txt"),
+            "  The \"wind\" picks up\n\nover the ridge\n",
+        )?;
+
+        let sounds = load_sounds(&sounds_path, SoundKind::Sound);
+        std::fs::remove_dir_all(&assets)?;
+
+        let sounds = sounds?;
+        assert_eq!(sounds.len(), 1);
+        assert_eq!(sounds[0].class_name, "LAAT_Ambient_Wind_Gust");
+        assert_eq!(sounds[0].pretty_name, "Wind Gust");
+        assert_eq!(sounds[0].file_name, "Wind Gust.ogg");
+        assert_eq!(sounds[0].sound_path, r"LAAT\Sounds\data\sounds\Ambient\Wind Gust.ogg");
+        assert_eq!(sounds[0].subtitle.as_deref(), Some(r#"The ""wind"" picks up over the ridge"#));
+        assert_eq!(sounds[0].duration, 1);
+        assert_eq!(sounds[0].db, "db-2");
+        assert!(sounds[0].transcode);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_nested_radio() -> Result<()> {
+        let radio_path = std::env::temp_dir()
+            .join(format!("laat-radio-{}", std::process::id()))
+            .join("radio");
+
+        write_wav(&radio_path.join("Command").join("Nested").join("Contact_Front.wav"));
+        let radio = load_sounds(&radio_path, SoundKind::Radio);
+
+        // Unsupported files are an error, which the plugin logs and skips
+        std::fs::write(radio_path.join("Command").join("Notes.md"), "")?;
+        let unsupported = load_sounds(&radio_path, SoundKind::Radio);
+
+        std::fs::remove_dir_all(radio_path.parent().unwrap())?;
+
+        let error = unsupported.unwrap_err().to_string();
+        assert!(error.contains("Unsupported format"), "{}", error);
+
+        let radio = radio?;
+        assert_eq!(radio.len(), 1);
+        assert_eq!(radio[0].class_name, "LAAT_Command_Nested_Contact_Front");
+        assert_eq!(radio[0].folder, Path::new("Command").join("Nested"));
+        assert_eq!(radio[0].sound_path, r"LAAT\Sounds\data\radio\Command\Nested\Contact_Front.ogg");
+        assert_eq!(radio[0].subtitle, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sounds_addon() -> Result<()> {
+        let addon = SoundsAddon {
+            prefix: "LAAT".to_string(),
+            addon_name: ADDON_NAME.to_string(),
+            required_addons: vec!["A3_Data_F".to_string()],
+            distance: 62.5,
+            sounds: vec![sound(SoundKind::Sound, "LAAT_Alarm")],
+            radio: vec![sound(SoundKind::Radio, "LAAT_Contact")],
+            sfx: vec![sound(SoundKind::Sfx, "LAAT_Generator")],
+        };
+
+        let config_cpp = create_handlebars()?.render("sounds_addon", &addon)?;
+        let config = Config::from_string(config_cpp, None, &Vec::new())?;
+
+        assert_eq!(class_names(&config, "CfgSounds"), vec!["LAAT_Alarm"]);
+        assert_eq!(class_names(&config, "CfgRadio"), vec!["LAAT_Contact"]);
+        assert_eq!(class_names(&config, "CfgSFX"), vec!["LAAT_Generator"]);
+
+        match config.inner().get("CfgSFX/LAAT_Generator/sound0") {
+            Some(ConfigEntry::ArrayEntry(array)) => {
+                assert_eq!(array.elements.len(), 8);
+                assert!(matches!(&array.elements[0], ConfigArrayElement::StringElement(path) if path == r"LAAT\Sounds\data\sfx\LAAT_Generator.ogg"));
+                assert!(matches!(&array.elements[3], ConfigArrayElement::FloatElement(distance) if (distance - 62.5).abs() < 1e-3));
+            }
+            _ => panic!("Missing CfgSFX sound0[]"),
+        }
+
+        match config.inner().get("CfgSFX/LAAT_Generator/sounds") {
+            Some(ConfigEntry::ArrayEntry(array)) => {
+                assert!(matches!(&array.elements[..], [ConfigArrayElement::StringElement(name)] if name == "sound0"));
+            }
+            _ => panic!("Missing CfgSFX sounds[]"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_collisions() {
+        // Classes only need to be unique within their config
+        let sounds = vec![
+            sound(SoundKind::Sound, "LAAT_Contact"),
+            sound(SoundKind::Radio, "LAAT_Contact"),
+            sound(SoundKind::Sfx, "LAAT_Contact"),
+        ];
+        assert!(check_collisions(&sounds).is_ok());
+
+        let sounds = vec![
+            sound(SoundKind::Radio, "LAAT_Contact"),
+            sound(SoundKind::Radio, "laat_contact"),
+        ];
+        let error = check_collisions(&sounds).unwrap_err().to_string();
+        assert!(error.contains("CfgRadio class name laat_contact"), "{}", error);
+    }
+}
@@ -0,0 +1,68 @@
+//! Config values shared by the plugins that generate addons.
+
+use std::path::Path;
+
+/// Escape a string for use inside a quoted config/SQM string
+pub fn config_string(value: &str) -> String {
+    value.replace('"', "\"\"")
+}
+
+/// Volume for `sound[]` arrays, e.g. "db-3.5"
+pub fn format_db(gain: f32) -> String {
+    let gain = (gain * 10.).round() / 10.;
+
+    if gain == 0. {
+        "db+0".to_string()
+    } else if gain < 0. {
+        format!("db{}", gain)
+    } else {
+        format!("db+{}", gain)
+    }
+}
+
+/// Folder part of the class names of assets in `folder`, a folder below `root`: every folder on
+/// the way joined by underscores, so `a/intro` and `b/intro` don't collide, or the name of `root`
+/// for assets directly in it
+pub fn folder_name(root: &Path, folder: &Path) -> String {
+    if folder.as_os_str().is_empty() {
+        return root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+
+    folder
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_string() {
+        assert_eq!(config_string(r#"[LAAT] "Zeus""#), r#"[LAAT] ""Zeus"""#);
+        assert_eq!(config_string("Plain"), "Plain");
+    }
+
+    #[test]
+    fn test_format_db() {
+        assert_eq!(format_db(0.), "db+0");
+        assert_eq!(format_db(-0.01), "db+0");
+        assert_eq!(format_db(-3.46), "db-3.5");
+        assert_eq!(format_db(2.), "db+2");
+    }
+
+    #[test]
+    fn test_folder_name() {
+        let root = Path::new("assets/music");
+
+        assert_eq!(folder_name(root, Path::new("")), "music");
+        assert_eq!(folder_name(root, Path::new("Combat")), "Combat");
+        assert_eq!(folder_name(root, &Path::new("a").join("intro")), "a_intro");
+        assert_ne!(folder_name(root, &Path::new("a").join("intro")), folder_name(root, &Path::new("b").join("intro")));
+    }
+}
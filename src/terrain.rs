@@ -0,0 +1,172 @@
+//! Catalogue of terrains, with their size and named spawn areas, for placing compositions.
+//!
+//! LAAT ships a catalogue of common terrains in `data/terrains.toml`. Projects can add terrains,
+//! or override spawn areas, with their own catalogue in the same format:
+//!
+//! ```toml
+//! [Altis]
+//! size = 30720
+//!
+//! [Altis.spawns]
+//! airfield = [14600, 0, 16750]
+//! ```
+
+use crate::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+const BUILTIN_TERRAINS: &str = include_str!("../data/terrains.toml");
+
+/// Placement name that resolves to the center of the terrain, unless the terrain has a spawn
+/// area with that name.
+pub const CENTER: &str = "center";
+
+/// A terrain catalogue file, keyed by world name
+pub type TerrainFile = HashMap<String, TerrainConfig>;
+
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+pub struct TerrainConfig {
+    /// Width and height of the terrain in meters. Required for terrains not in the built-in
+    /// catalogue.
+    size: Option<f32>,
+
+    /// Named spawn areas, as `[x, altitude, y]` positions
+    #[serde(default)]
+    spawns: HashMap<String, (f32, f32, f32)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    name: String,
+    size: f32,
+    spawns: HashMap<String, (f32, f32, f32)>,
+}
+
+impl Terrain {
+    pub fn center(&self) -> (f32, f32, f32) {
+        (self.size / 2., 0., self.size / 2.)
+    }
+
+    /// Position of a named spawn area, or of the center for `center`
+    pub fn spawn(&self, name: &str) -> Result<(f32, f32, f32)> {
+        if let Some(position) = self.spawns.get(&name.to_lowercase()) {
+            return Ok(*position);
+        }
+
+        if name.eq_ignore_ascii_case(CENTER) {
+            return Ok(self.center());
+        }
+
+        let mut known: Vec<&str> = self.spawns.keys().map(String::as_str).collect();
+        known.sort_unstable();
+
+        Err(format!(
+            "Unknown spawn area \"{}\" on {}, expected one of: {}, {}",
+            name,
+            self.name,
+            CENTER,
+            known.join(", ")
+        )
+        .into())
+    }
+
+    /// Whether an `[x, altitude, y]` position is within the terrain's bounds
+    pub fn contains(&self, (x, _, y): (f32, f32, f32)) -> bool {
+        (0. ..=self.size).contains(&x) && (0. ..=self.size).contains(&y)
+    }
+
+    pub fn check_bounds(&self, position: (f32, f32, f32)) -> Result<()> {
+        if self.contains(position) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Position [{}, {}, {}] is outside of {}, which is {}m wide",
+                position.0, position.1, position.2, self.name, self.size
+            )
+            .into())
+        }
+    }
+}
+
+/// Terrains keyed by lowercase world name
+#[derive(Clone, Debug, Default)]
+pub struct TerrainCatalogue {
+    terrains: HashMap<String, Terrain>,
+}
+
+impl TerrainCatalogue {
+    /// The catalogue shipped with LAAT
+    pub fn builtin() -> Result<Self> {
+        let file: TerrainFile = toml::from_str(BUILTIN_TERRAINS)?;
+
+        let mut catalogue = TerrainCatalogue::default();
+        catalogue.extend(file)?;
+
+        Ok(catalogue)
+    }
+
+    /// The built-in catalogue, extended with a project's catalogue file if given
+    #[instrument(err)]
+    pub async fn load(path: Option<&Path>) -> Result<Self> {
+        let mut catalogue = TerrainCatalogue::builtin()?;
+
+        if let Some(path) = path {
+            let mut file = tokio::fs::File::open(path)
+                .await
+                .map_err(|why| format!("Failed to open terrain catalogue {}: {}", path.display(), why))?;
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).await?;
+
+            let file: TerrainFile = toml::from_str(&contents)
+                .map_err(|why| format!("Invalid terrain catalogue {}: {}", path.display(), why))?;
+
+            catalogue.extend(file)?;
+        }
+
+        Ok(catalogue)
+    }
+
+    /// Add terrains, or update the size and spawn areas of known terrains
+    pub fn extend(&mut self, file: TerrainFile) -> Result<()> {
+        for (name, config) in file {
+            let key = name.to_lowercase();
+            let spawns = config
+                .spawns
+                .into_iter()
+                .map(|(spawn, position)| (spawn.to_lowercase(), position));
+
+            match self.terrains.get_mut(&key) {
+                Some(terrain) => {
+                    if let Some(size) = config.size {
+                        terrain.size = size;
+                    }
+
+                    terrain.spawns.extend(spawns);
+                }
+                None => {
+                    let size = config
+                        .size
+                        .ok_or_else(|| format!("Terrain {} is missing its size", name))?;
+
+                    self.terrains.insert(
+                        key,
+                        Terrain {
+                            name,
+                            size,
+                            spawns: spawns.collect(),
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, map_name: &str) -> Option<&Terrain> {
+        self.terrains.get(&map_name.to_lowercase())
+    }
+}
@@ -0,0 +1,168 @@
+//! Workspaces for building several mods from one repository.
+//!
+//! A workspace LAAT.toml contains a `[workspace]` table listing member project folders, each with
+//! their own LAAT.toml (prefix, workshop ID, keys, etc.), and any asset folders the members share.
+//!
+//! ```toml
+//! [workspace]
+//! members = ["core", "client", "server"]
+//! shared_assets = ["shared/assets"]
+//! ```
+
+use crate::config::{self, Manifest};
+use crate::LaatCompiler;
+use crate::Result;
+use std::path::{Path, PathBuf};
+
+const LAAT_TOML: &str = "LAAT.toml";
+
+/// A single mod project in a workspace
+pub struct Member {
+    /// Folder of the member project. `None` for a standalone project, which runs in the current
+    /// directory.
+    path: Option<PathBuf>,
+    compiler: LaatCompiler,
+}
+
+impl Member {
+    /// Name of the member, the member's folder name or the prefix for standalone projects
+    pub fn name(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.compiler.config.prefix.clone())
+    }
+
+    pub fn compiler(&self) -> &LaatCompiler {
+        &self.compiler
+    }
+
+    /// Switch into the member's folder, so relative paths in its LAAT.toml resolve correctly
+    pub fn enter(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            debug!("Entering {}", path.display());
+            std::env::set_current_dir(path)
+                .map_err(|why| format!("Failed to enter {}: {}", path.display(), why))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The set of mod projects to run commands over
+pub struct Workspace {
+    members: Vec<Member>,
+}
+
+impl Workspace {
+    /// Load a LAAT.toml, which may either be a standalone project or a workspace.
+    ///
+    /// `member` limits a workspace to the member with that folder name or prefix, and `profile`
+    /// applies that `[profile.<name>]` to every project.
+    #[instrument(err)]
+    pub async fn from_path(
+        path: PathBuf,
+        member: Option<String>,
+        profile: Option<String>,
+    ) -> Result<Self> {
+        let workspace = match config::get_manifest_from_path(&path).await? {
+            Manifest::Project(mut config) => {
+                if let Some(member) = &member {
+                    return Err(format!(
+                        "--member {} needs a workspace, but {} has no [workspace]",
+                        member,
+                        path.display()
+                    )
+                    .into());
+                }
+
+                if let Some(profile) = &profile {
+                    config.apply_profile(profile)?;
+                }
+
+                return Ok(Workspace {
+                    members: vec![Member {
+                        path: None,
+                        compiler: LaatCompiler::from_config(*config)?,
+                    }],
+                })
+            }
+            Manifest::Workspace(workspace) => workspace,
+        };
+
+        let root = std::env::current_dir()?.join(path.parent().unwrap_or_else(|| Path::new("")));
+
+        let shared_assets: Vec<String> = workspace
+            .shared_assets
+            .iter()
+            .map(|assets| root.join(assets).display().to_string())
+            .collect();
+
+        let mut members = Vec::new();
+
+        for member_path in workspace.members {
+            let member_path = root.join(member_path);
+
+            let mut config = config::get_config_from_path(member_path.join(LAAT_TOML)).await?;
+            config.shared_assets.extend(shared_assets.iter().cloned());
+
+            if let Some(profile) = &profile {
+                config
+                    .apply_profile(profile)
+                    .map_err(|why| format!("{}: {}", member_path.display(), why))?;
+            }
+
+            let project = Member {
+                path: Some(member_path),
+                compiler: LaatCompiler::from_config(config)?,
+            };
+
+            if let Some(filter) = &member {
+                if filter != &project.name() && filter != &project.compiler.config.prefix {
+                    continue;
+                }
+            }
+
+            info!("Loaded workspace member: {}", project.name());
+            members.push(project);
+        }
+
+        if members.is_empty() {
+            return match member {
+                Some(name) => Err(format!("No workspace member named: {}", name).into()),
+                None => Err("Workspace has no members".into()),
+            };
+        }
+
+        Ok(Workspace { members })
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_member_without_workspace() -> Result<()> {
+        let folder = std::env::temp_dir().join(format!("laat-workspace-{}", std::process::id()));
+        std::fs::create_dir_all(&folder)?;
+
+        let path = folder.join(LAAT_TOML);
+        std::fs::write(&path, "prefix = \"LAAT\"\nname = \"LAAT Test Mod\"\nplugins = []\n")?;
+
+        let result = Workspace::from_path(path, Some("client".to_string()), None).await;
+        std::fs::remove_dir_all(&folder)?;
+
+        match result {
+            Err(why) => assert!(why.to_string().contains("has no [workspace]"), "{}", why),
+            Ok(_) => panic!("--member without a workspace should fail"),
+        }
+
+        Ok(())
+    }
+}